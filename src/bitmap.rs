@@ -0,0 +1,204 @@
+use bit_set::BitSet;
+
+pub(crate) const WORD_BITS: usize = u64::BITS as usize;
+
+/// Fixed length bitmap packed into 64 bit words, bit `i` being bit `i % 64` of word `i / 64`.
+/// Bits past `len` are always clear.
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct Bitmap {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Bitmap {
+    /// All `len` bits clear.
+    pub fn new(len: usize) -> Self {
+        Self {
+            words: vec![0; (len + WORD_BITS - 1) / WORD_BITS],
+            len,
+        }
+    }
+
+    /// Bits of `bits` below `len`, the others are dropped.
+    pub fn from_bitset(bits: &BitSet, len: usize) -> Self {
+        let mut bitmap = Self::new(len);
+        for idx in bits.iter().take_while(|idx| *idx < len) {
+            bitmap.insert(idx);
+        }
+        bitmap
+    }
+
+    /// Words as returned by `words`, resized to fit `len` bits. Bits past `len` are cleared.
+    pub fn from_words(mut words: Vec<u64>, len: usize) -> Self {
+        words.resize((len + WORD_BITS - 1) / WORD_BITS, 0);
+        if len % WORD_BITS != 0 {
+            if let Some(last) = words.last_mut() {
+                *last &= (1u64 << (len % WORD_BITS)) - 1;
+            }
+        }
+        Self { words, len }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    /// False for bits past `len`.
+    #[inline]
+    pub fn contains(&self, idx: usize) -> bool {
+        idx < self.len && self.words[idx / WORD_BITS] & (1 << (idx % WORD_BITS)) != 0
+    }
+
+    /// Panics if `idx` is not less than `len`.
+    #[inline]
+    pub fn insert(&mut self, idx: usize) {
+        assert!(idx < self.len, "Bit {} is out of the bitmap of {} bits.", idx, self.len);
+        self.words[idx / WORD_BITS] |= 1 << (idx % WORD_BITS);
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// Indices of the set bits in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(word_idx, word)| {
+            let mut rest = *word;
+            std::iter::from_fn(move || {
+                if rest == 0 { return None; }
+                let bit = rest.trailing_zeros() as usize;
+                rest &= rest - 1;
+                Some(word_idx * WORD_BITS + bit)
+            })
+        })
+    }
+
+    /// Whether any bit in `start..end` is set, testing a whole word at a time.
+    pub fn any_in(&self, start: usize, end: usize) -> bool {
+        let end = end.min(self.len);
+        let mut idx = start;
+        while idx < end {
+            let offset = idx % WORD_BITS;
+            let len = (WORD_BITS - offset).min(end - idx);
+            let mask = if len == WORD_BITS { !0 } else { ((1u64 << len) - 1) << offset };
+            if self.words[idx / WORD_BITS] & mask != 0 { return true; }
+            idx += len;
+        }
+        false
+    }
+
+    /// Bits set in either. Panics if the lengths differ.
+    pub fn union(&self, other: &Bitmap) -> Bitmap {
+        self.combine(other, |a, b| a | b)
+    }
+
+    /// Bits set in both. Panics if the lengths differ.
+    pub fn intersection(&self, other: &Bitmap) -> Bitmap {
+        self.combine(other, |a, b| a & b)
+    }
+
+    /// Every bit below `len` flipped.
+    pub fn complement(&self) -> Bitmap {
+        Bitmap::from_words(self.words.iter().map(|word| !word).collect(), self.len)
+    }
+
+    fn combine<F>(&self, other: &Bitmap, op: F) -> Bitmap where F: Fn(u64, u64) -> u64 {
+        assert_eq!(self.len, other.len, "Bitmaps of different lengths.");
+        Bitmap {
+            words: self.words.iter().zip(other.words.iter()).map(|(a, b)| op(*a, *b)).collect(),
+            len: self.len,
+        }
+    }
+
+    /// Number of bits set in both.
+    pub fn intersection_count(&self, other: &Bitmap) -> usize {
+        self.words.iter().zip(other.words.iter()).map(|(a, b)| (a & b).count_ones() as usize).sum()
+    }
+
+    /// Number of bits set in either.
+    pub fn union_count(&self, other: &Bitmap) -> usize {
+        let (longer, shorter) = if self.words.len() < other.words.len() { (other, self) } else { (self, other) };
+        longer.words.iter().enumerate()
+            .map(|(i, a)| (a | shorter.words.get(i).copied().unwrap_or(0)).count_ones() as usize)
+            .sum()
+    }
+}
+
+/// Keeps every bit of the set, the length being the capacity of the set.
+impl From<BitSet> for Bitmap {
+    fn from(bits: BitSet) -> Self {
+        let len = bits.get_ref().len();
+        Bitmap::from_bitset(&bits, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bit_set::BitSet;
+    use super::Bitmap;
+
+    #[test]
+    fn can_set_and_iterate_bits() {
+        let mut bitmap = Bitmap::new(130);
+        assert_eq!(bitmap.words().len(), 3);
+        for idx in [0, 63, 64, 129] {
+            bitmap.insert(idx);
+        }
+        assert!(bitmap.contains(63));
+        assert!(!bitmap.contains(62));
+        assert!(!bitmap.contains(130));
+        assert_eq!(bitmap.count_ones(), 4);
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), vec![0, 63, 64, 129]);
+
+        assert!(bitmap.any_in(1, 64));
+        assert!(!bitmap.any_in(1, 63));
+        assert!(!bitmap.any_in(65, 129));
+        assert!(bitmap.any_in(65, 1000));
+    }
+
+    #[test]
+    fn can_convert_from_bitset() {
+        let mut bits = BitSet::new();
+        bits.insert(3);
+        bits.insert(70);
+        let bitmap = Bitmap::from_bitset(&bits, 10);
+        assert_eq!(bitmap.len(), 10);
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), vec![3]);
+
+        let bitmap: Bitmap = bits.into();
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), vec![3, 70]);
+
+        let bitmap = Bitmap::from_words(vec![!0, !0], 70);
+        assert_eq!(bitmap.count_ones(), 70);
+        assert_eq!(bitmap, Bitmap::from_words(bitmap.words().to_vec(), 70));
+    }
+
+    #[test]
+    fn can_count_union_and_intersection() {
+        let mut a = Bitmap::new(100);
+        let mut b = Bitmap::new(100);
+        for idx in [1, 50, 99] {
+            a.insert(idx);
+        }
+        for idx in [50, 70] {
+            b.insert(idx);
+        }
+        assert_eq!(a.intersection_count(&b), 1);
+        assert_eq!(a.union_count(&b), 4);
+        assert_eq!(a.intersection(&b).iter().collect::<Vec<_>>(), vec![50]);
+        assert_eq!(a.union(&b).iter().collect::<Vec<_>>(), vec![1, 50, 70, 99]);
+        assert_eq!(b.complement().count_ones(), 98);
+        assert!(!b.complement().contains(70));
+    }
+}
@@ -2,37 +2,80 @@ use std::mem::size_of;
 
 use egui::{ColorImage, Color32};
 
-const USIZE_BYTE_LEN: usize = size_of::<usize>();
+// The header is MAGIC, VERSION, then width and height. Dimensions are stored as little endian u32
+// so that the format does not depend on the platform. Bump VERSION whenever the encoding changes.
+const MAGIC: [u8; 4] = *b"CIMG";
+const VERSION: u8 = 1;
+const DIM_BYTE_LEN: usize = size_of::<u32>();
+const HEADER_BYTE_LEN: usize = MAGIC.len() + 1 + DIM_BYTE_LEN * 2;
 const COLOR_BYTE_LEN: usize = Color32::BLACK.to_array().len();
 
-pub fn img_to_u8(img: &ColorImage) -> Vec<u8> {
+/// Fails if a dimension does not fit in the u32 of the header.
+pub fn img_to_u8(img: &ColorImage) -> Result<Vec<u8>, ConvertError> {
     let [width, height] = img.size;
-    let mut vec = vec![0u8; USIZE_BYTE_LEN * 2 + width * height * COLOR_BYTE_LEN];
+    let (w, h) = match (u32::try_from(width), u32::try_from(height)) {
+        (Ok(w), Ok(h)) => (w, h),
+        _ => return Err(ConvertError::DimensionTooLarge { width, height }),
+    };
+    let mut vec = vec![0u8; HEADER_BYTE_LEN + width * height * COLOR_BYTE_LEN];
     let mut idx: usize = 0;
 
-    vec[idx..(idx + USIZE_BYTE_LEN)].copy_from_slice(&width.to_ne_bytes());
-    idx += USIZE_BYTE_LEN;
-    vec[idx..(idx + USIZE_BYTE_LEN)].copy_from_slice(&height.to_ne_bytes());
-    idx += USIZE_BYTE_LEN;
+    vec[idx..(idx + MAGIC.len())].copy_from_slice(&MAGIC);
+    idx += MAGIC.len();
+    vec[idx] = VERSION;
+    idx += 1;
+
+    vec[idx..(idx + DIM_BYTE_LEN)].copy_from_slice(&w.to_le_bytes());
+    idx += DIM_BYTE_LEN;
+    vec[idx..(idx + DIM_BYTE_LEN)].copy_from_slice(&h.to_le_bytes());
+    idx += DIM_BYTE_LEN;
 
     for c in img.pixels.iter() {
         vec[idx..(idx + COLOR_BYTE_LEN)].copy_from_slice(&c.to_array());
         idx += COLOR_BYTE_LEN;
     }
-    vec
+    Ok(vec)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConvertError {
+    TooShortForHeader { len: usize },
+    BadMagic,
+    UnsupportedVersion { version: u8 },
+    PixelDataLengthMismatch { width: usize, height: usize, expected: Option<usize>, actual: usize },
+    DimensionTooLarge { width: usize, height: usize },
 }
 
-pub fn u8_to_img(bin: &[u8]) -> ColorImage {
+pub fn u8_to_img(bin: &[u8]) -> Result<ColorImage, ConvertError> {
+    if bin.len() < HEADER_BYTE_LEN {
+        return Err(ConvertError::TooShortForHeader { len: bin.len() });
+    }
+
     let mut idx: usize = 0;
-    let mut usize_buf = [0u8; USIZE_BYTE_LEN];
-    usize_buf.copy_from_slice(&bin[idx..(idx + USIZE_BYTE_LEN)]);
-    let width = usize::from_ne_bytes(usize_buf);
-    idx += USIZE_BYTE_LEN;
+    if bin[idx..(idx + MAGIC.len())] != MAGIC {
+        return Err(ConvertError::BadMagic);
+    }
+    idx += MAGIC.len();
+    if bin[idx] != VERSION {
+        return Err(ConvertError::UnsupportedVersion { version: bin[idx] });
+    }
+    idx += 1;
+    let mut dim_buf = [0u8; DIM_BYTE_LEN];
+    dim_buf.copy_from_slice(&bin[idx..(idx + DIM_BYTE_LEN)]);
+    let width = u32::from_le_bytes(dim_buf) as usize;
+    idx += DIM_BYTE_LEN;
 
-    let mut usize_buf = [0u8; USIZE_BYTE_LEN];
-    usize_buf.copy_from_slice(&bin[idx..(idx + USIZE_BYTE_LEN)]);
-    let height = usize::from_ne_bytes(usize_buf);
-    idx += USIZE_BYTE_LEN;
+    let mut dim_buf = [0u8; DIM_BYTE_LEN];
+    dim_buf.copy_from_slice(&bin[idx..(idx + DIM_BYTE_LEN)]);
+    let height = u32::from_le_bytes(dim_buf) as usize;
+    idx += DIM_BYTE_LEN;
+
+    let pixel_count = width.checked_mul(height);
+    let expected = pixel_count.and_then(|c| c.checked_mul(COLOR_BYTE_LEN));
+    let actual = bin.len() - idx;
+    if expected != Some(actual) {
+        return Err(ConvertError::PixelDataLengthMismatch { width, height, expected, actual });
+    }
 
     let mut color_buf = [0u8; COLOR_BYTE_LEN];
     let pixel_count = width * height;
@@ -43,16 +86,16 @@ pub fn u8_to_img(bin: &[u8]) -> ColorImage {
         pixels.push(Color32::from_rgba_premultiplied(color_buf[0], color_buf[1], color_buf[2], color_buf[3]));
     }
 
-    ColorImage {
+    Ok(ColorImage {
         size: [width, height],
-        pixels: pixels,
-    }
+        pixels,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use egui::{Color32, ColorImage};
-    use super::{img_to_u8, u8_to_img};
+    use super::{img_to_u8, u8_to_img, ConvertError};
 
     #[test]
     fn can_convert() {
@@ -64,11 +107,68 @@ mod tests {
             pixels: pixels.clone(),
         };
 
-        let bin = img_to_u8(&img);
-        let cvt_img = u8_to_img(&bin);
+        let bin = img_to_u8(&img).unwrap();
+        let cvt_img = u8_to_img(&bin).unwrap();
 
         assert_eq!(cvt_img.width(), width);
         assert_eq!(cvt_img.height(), height);
         assert_eq!(cvt_img.pixels, pixels);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn can_decode_little_endian() {
+        let bin: Vec<u8> = vec![
+            b'C', b'I', b'M', b'G', 1,
+            2, 0, 0, 0, // width
+            1, 0, 0, 0, // height
+            255, 0, 0, 255,
+            0, 0, 0, 0,
+        ];
+        let img = u8_to_img(&bin).unwrap();
+        assert_eq!(img.size, [2, 1]);
+        assert_eq!(img.pixels, vec![Color32::RED, Color32::TRANSPARENT]);
+        assert_eq!(img_to_u8(&img).unwrap(), bin);
+    }
+
+    #[test]
+    fn rejects_short_input() {
+        assert_eq!(u8_to_img(&[]), Err(ConvertError::TooShortForHeader { len: 0 }));
+        assert_eq!(u8_to_img(&[b'C', b'I', b'M', b'G', 1, 2, 0, 0, 0, 1, 0, 0]), Err(ConvertError::TooShortForHeader { len: 12 }));
+    }
+
+    #[test]
+    fn rejects_pixel_length_mismatch() {
+        let bin: Vec<u8> = vec![
+            b'C', b'I', b'M', b'G', 1,
+            2, 0, 0, 0, // width
+            2, 0, 0, 0, // height
+            255, 0, 0, 255,
+            0, 0, 0, 0,
+        ];
+        assert_eq!(
+            u8_to_img(&bin),
+            Err(ConvertError::PixelDataLengthMismatch { width: 2, height: 2, expected: Some(16), actual: 8 })
+        );
+    }
+
+    #[test]
+    fn rejects_other_format() {
+        let img = ColorImage { size: [1, 1], pixels: vec![Color32::RED] };
+        let mut bin = img_to_u8(&img).unwrap();
+        assert_eq!(&bin[0..5], b"CIMG\x01");
+
+        bin[4] = 2;
+        assert_eq!(u8_to_img(&bin), Err(ConvertError::UnsupportedVersion { version: 2 }));
+
+        // Entries written before the header was introduced.
+        let old: Vec<u8> = vec![2, 0, 0, 0, 1, 0, 0, 0, 255, 0, 0, 255, 0, 0, 0, 0];
+        assert_eq!(u8_to_img(&old), Err(ConvertError::BadMagic));
+    }
+
+    #[test]
+    fn rejects_dimension_over_u32() {
+        let width = u32::MAX as usize + 1;
+        let img = ColorImage { size: [width, 0], pixels: Vec::new() };
+        assert_eq!(img_to_u8(&img), Err(ConvertError::DimensionTooLarge { width, height: 0 }));
+    }
+}
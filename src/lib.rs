@@ -1,42 +1,127 @@
-use std::path::Path;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 use bit_set::BitSet;
-use egui::{ColorImage, Color32, TextureHandle, Vec2, Context, Rect, Pos2, TextureId, TextureOptions};
+use egui::{ColorImage, Color32, TextureHandle, Vec2, Context, Rect, Pos2, TextureId, TextureOptions, Ui, Id, Sense, Response, Widget};
+use bitmap::{Bitmap, WORD_BITS};
 use img_converter::{img_to_u8, u8_to_img};
 use local_file_cache::LocalFileCache;
 use sha::sha256::Sha256;
 
+pub mod bitmap;
 pub mod img_converter;
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(from = "Pixels2DRepr", into = "Pixels2DRepr"))]
 pub struct Pixels2D {
-    bits: BitSet,
+    bits: Bitmap,
     rect: Rect,
+    // Opaque pixel count of the rect (0, 0)-(x, y) at x + y * (width + 1).
+    summed_area: Option<Vec<u32>>,
+}
+
+// The summed-area table is derived from the bits, so it does not take part in the comparison.
+impl PartialEq for Pixels2D {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits == other.bits && self.rect == other.rect
+    }
 }
 
 impl Pixels2D {
-    fn new(bits: BitSet, rect: Rect) -> Self {
+    /// `bits` holds the pixels row by row, bit `x + y * width` being set for an opaque pixel.
+    pub fn new(bits: BitSet, rect: Rect) -> Self {
+        let len = (rect.width() as usize) * (rect.height() as usize);
+        Self::from_bitmap(Bitmap::from_bitset(&bits, len), rect)
+    }
+
+    /// Like `new` but takes the packed bitmap as is. Panics unless it has exactly one bit per pixel.
+    pub fn from_bitmap(bits: Bitmap, rect: Rect) -> Self {
+        let len = (rect.width() as usize) * (rect.height() as usize);
+        assert_eq!(bits.len(), len, "The bitmap must have {} bits for {:?}.", len, rect.size());
         Self {
-            bits, rect,
+            bits, rect, summed_area: None,
+        }
+    }
+
+    /// Builds a summed-area table so that `pixel_count` and `contains_pixel` take constant time instead
+    /// of scanning the rect, at the cost of 4 bytes per pixel. This also speeds up building the tree
+    /// of a `BitImg` from these pixels, notably for dense masks (see `TreeOptions::summed_area_table`).
+    /// Serialization only records that the table exists and it is rebuilt on deserialization.
+    pub fn with_summed_area_table(mut self) -> Self {
+        let w = self.rect.width() as usize;
+        let h = self.rect.height() as usize;
+        let stride = w + 1;
+        let mut table = vec![0u32; stride * (h + 1)];
+        for y in 0..h {
+            let mut row_count: u32 = 0;
+            for x in 0..w {
+                if self.bits.contains(x + y * w) { row_count += 1; }
+                table[(x + 1) + (y + 1) * stride] = table[(x + 1) + y * stride] + row_count;
+            }
         }
+        self.summed_area = Some(table);
+        self
+    }
+
+    #[inline]
+    pub fn has_summed_area_table(&self) -> bool {
+        self.summed_area.is_some()
+    }
+
+    // Count in the summed-area table of the rect that must be already clipped into the image.
+    fn summed_area_count(&self, table: &[u32], rect: &Rect) -> usize {
+        let stride = self.rect.width() as usize + 1;
+        let x0 = rect.min.x as usize;
+        let y0 = rect.min.y as usize;
+        let x1 = x0 + rect.width() as usize;
+        let y1 = y0 + rect.height() as usize;
+        (table[x1 + y1 * stride] + table[x0 + y0 * stride] - table[x0 + y1 * stride] - table[x1 + y0 * stride]) as usize
     }
     
     pub fn dump(&self) {
+        use std::io::Write;
+        let mut ascii = String::new();
+        let _ = self.write_ascii(&mut ascii);
+        let _ = std::io::stdout().lock().write_all(ascii.as_bytes());
+    }
+
+    /// Writes the mask row by row, `X` for opaque and a space for transparent pixels, each row followed by a newline.
+    pub fn write_ascii<W>(&self, w: &mut W) -> std::fmt::Result where W: std::fmt::Write {
         for y in 0..(self.rect.height() as usize) {
             for x in 0..(self.rect.width() as usize) {
-                print!("{}", if self.pixel_at(x, y) { "X" } else {" "});
+                w.write_char(if self.pixel_at(x, y) { 'X' } else { ' ' })?;
             }
-            println!("");
+            w.write_char('\n')?;
         }
+        Ok(())
     }
 
     #[inline]
     pub fn pixel_at(&self, x: usize, y: usize) -> bool {
-        self.bits.contains(x + y * (self.rect.width() as usize))
+        let w = self.rect.width() as usize;
+        if w <= x || self.rect.height() as usize <= y { return false; }
+        self.bits.contains(x + y * w)
+    }
+
+    /// Number of opaque pixels in the whole image, counted from the set bits.
+    #[inline]
+    pub fn total_opaque(&self) -> usize {
+        self.bits.count_ones()
     }
 
     pub fn pixel_count(&self, rect: Rect) -> usize {
+        let rect = match self.clip(&rect) {
+            Some(clipped) => clipped,
+            None => return 0,
+        };
+        if let Some(table) = &self.summed_area {
+            return self.summed_area_count(table, &rect);
+        }
         let start_x = rect.min.x as usize;
         let start_y = rect.min.y as usize;
         let w = rect.width() as usize;
@@ -54,29 +139,54 @@ impl Pixels2D {
         count        
     }
 
+    // Clamps rect into the image so that its coordinates can be safely cast to usize.
+    fn clip(&self, rect: &Rect) -> Option<Rect> {
+        let clipped = Rect::from_min_max(rect.min.max(self.rect.min), rect.max.min(self.rect.max));
+        if clipped.is_positive() { Some(clipped) } else { None }
+    }
+
     pub fn contains_pixel(&self, rect: &Rect) -> bool {
-        let covered_both = rect.intersect(self.rect);
-        if covered_both == Rect::NOTHING { return false; }
+        let covered_both = match self.clip(rect) {
+            Some(clipped) => clipped,
+            None => return false,
+        };
+        if let Some(table) = &self.summed_area {
+            return self.summed_area_count(table, &covered_both) != 0;
+        }
 
+        let image_w = self.rect.width() as usize;
         let start_x = covered_both.min.x as usize;
         let start_y = covered_both.min.y as usize;
         let w = covered_both.width() as usize;
         let h = covered_both.height() as usize;
 
-        for y in start_y..(start_y + h) {
-            for x in start_x..(start_x + w) {
-                if self.pixel_at(x, y) { return true; }
+        (start_y..(start_y + h)).any(|y| {
+            let row_start = start_x + y * image_w;
+            self.bits.any_in(row_start, row_start + w)
+        })
+    }
+}
+
+/// The grid of `write_ascii` with the rows separated by newlines, without one after the last row.
+impl std::fmt::Display for Pixels2D {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for y in 0..(self.rect.height() as usize) {
+            if y != 0 { f.write_char('\n')?; }
+            for x in 0..(self.rect.width() as usize) {
+                f.write_char(if self.pixel_at(x, y) { 'X' } else { ' ' })?;
             }
         }
-
-        return false;
+        Ok(())
     }
 }
 
+/// Acceleration structure of a `BitImg`: each node covers a rect of the image, counts its opaque
+/// pixels and is split into children tiling it. The whole tree is built by the `BitImg` constructor
+/// and never changes afterwards. Lazy trees (`TreeOptions::lazy`) use a separate internal structure.
 #[derive(PartialEq, Debug, Clone)]
 pub enum LayeredRect {
     Leaf { rect: Rect, pixel_count: usize },
-    Node { rect: Rect, children: [Box<LayeredRect>; 2], pixel_count: usize },
+    Node { rect: Rect, children: Vec<LayeredRect>, pixel_count: usize },
 }
 
 pub fn split_horizontal(rect: &Rect) -> [Rect; 2] {
@@ -101,55 +211,252 @@ pub fn split_vertical(rect: &Rect) -> [Rect; 2] {
     [ top_rect, bottom_rect ]
 }
 
-const MIN_NODE_SIZE: f32 = 3.;
+pub const DEFAULT_MIN_NODE_SIZE: f32 = 3.;
+
+/// Options for building the `LayeredRect` tree of a `BitImg`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct TreeOptions {
+    /// Stops splitting at this depth (the root is at depth 0). A lower depth uses less memory
+    /// but leaves cover larger regions that have to be scanned pixel by pixel on query.
+    pub max_depth: Option<usize>,
+    /// Rects no wider and no taller than this become leaves. A larger size builds a smaller tree
+    /// faster, which suits large sparse masks, while queries scan more pixels in each leaf.
+    pub min_node_size: f32,
+    /// Builds the children of a node on the first `contains_pixel` descending into it instead of
+    /// building the whole tree up front, which pays off for huge masks queried only in small regions.
+    /// Only `contains_pixel` uses the lazy tree. Other queries scan the pixels without a tree, and the
+    /// tree introspection (`tree_stats`, `to_dot`, `tree_to_svg`, `leaves_intersecting`) returns `None`.
+    pub lazy: bool,
+    pub split_strategy: SplitStrategy,
+    /// Builds the summed-area table of the pixels (`Pixels2D::with_summed_area_table`) before the tree,
+    /// so that counting the pixels of each node takes constant time. The table is kept for later queries.
+    pub summed_area_table: bool,
+}
+
+/// How a node of the `LayeredRect` tree is divided into children.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum SplitStrategy {
+    /// Halves the width until it reaches the minimum node size, then the height, into two children.
+    #[default]
+    BinaryAlternating,
+    /// Halves the width and the height at once into up to four children, making the tree shallower.
+    Quadtree,
+}
+
+impl Default for TreeOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            min_node_size: DEFAULT_MIN_NODE_SIZE,
+            lazy: false,
+            split_strategy: SplitStrategy::default(),
+            summed_area_table: false,
+        }
+    }
+}
+
+impl TreeOptions {
+    // Children of a node at depth, None for a leaf.
+    fn split(&self, rect: &Rect, depth: usize) -> Option<Vec<Rect>> {
+        if self.max_depth.map_or(false, |max_depth| max_depth <= depth) { return None; }
+        // An image without pixels is a single empty leaf.
+        if rect.width() < 1. || rect.height() < 1. { return None; }
+        let split_width = self.min_node_size < rect.width();
+        let split_height = self.min_node_size < rect.height();
+        match self.split_strategy {
+            SplitStrategy::BinaryAlternating if split_width => Some(split_horizontal(rect).to_vec()),
+            SplitStrategy::BinaryAlternating if split_height => Some(split_vertical(rect).to_vec()),
+            SplitStrategy::Quadtree if split_width && split_height =>
+                Some(split_horizontal(rect).iter().flat_map(split_vertical).collect()),
+            SplitStrategy::Quadtree if split_width => Some(split_horizontal(rect).to_vec()),
+            SplitStrategy::Quadtree if split_height => Some(split_vertical(rect).to_vec()),
+            _ => None,
+        }
+    }
+}
+
+// Node of a lazily built tree. Children are built once, on the first descent.
+struct LazyNode {
+    rect: Rect,
+    depth: usize,
+    pixel_count: usize,
+    children: RwLock<Option<Vec<LazyNode>>>,
+}
+
+impl LazyNode {
+    fn new(rect: Rect, depth: usize, pixels: &Pixels2D) -> Self {
+        Self {
+            rect, depth,
+            pixel_count: pixels.pixel_count(rect),
+            children: RwLock::new(None),
+        }
+    }
+
+    #[cfg(test)]
+    fn built_count(&self) -> usize {
+        1 + self.children.read().unwrap().as_ref().map_or(0, |children| children.iter().map(|child| child.built_count()).sum())
+    }
+}
+
+struct LazyTree {
+    root: LazyNode,
+    options: TreeOptions,
+}
 
 impl LayeredRect {
+    #[cfg(test)]
     fn new(rect: Rect, bit_img: &Pixels2D) -> Self {
-        if MIN_NODE_SIZE < rect.width() {
-            let [b0, b1] = split_horizontal(&rect);
-            let l0 = LayeredRect::new(b0, bit_img);
-            let l1 = LayeredRect::new(b1, bit_img);
-            LayeredRect::Node {
-                rect,
-                pixel_count: l0.pixel_count() + l1.pixel_count(),
-                children: [Box::new(l0), Box::new(l1)]
-            }
-        } else if MIN_NODE_SIZE < rect.height() {
-            let [b0, b1] = split_vertical(&rect);
-            let l0 = LayeredRect::new(b0, bit_img);
-            let l1 = LayeredRect::new(b1, bit_img);
-            LayeredRect::Node {
-                rect,
-                pixel_count: l0.pixel_count() + l1.pixel_count(),
-                children: [Box::new(l0), Box::new(l1)]
-            }
-        } else {
-            let cnt = bit_img.pixel_count(rect);
-            LayeredRect::Leaf {
-                rect,
-                pixel_count: cnt,
+        Self::build(rect, bit_img, &TreeOptions::default(), 0)
+    }
+
+    // Builds the tree with an explicit work stack instead of recursion so that the depth of the tree
+    // does not hit the thread stack. Children are built before their parent (post-order).
+    fn build(rect: Rect, bit_img: &Pixels2D, options: &TreeOptions, depth: usize) -> Self {
+        enum Work {
+            Visit(Rect, usize),
+            Join(Rect, usize),
+        }
+
+        let mut work = vec![Work::Visit(rect, depth)];
+        let mut built: Vec<LayeredRect> = Vec::new();
+        while let Some(w) = work.pop() {
+            match w {
+                Work::Visit(rect, depth) => {
+                    match options.split(&rect, depth) {
+                        Some(split) => {
+                            work.push(Work::Join(rect, split.len()));
+                            work.extend(split.into_iter().rev().map(|child| Work::Visit(child, depth + 1)));
+                        },
+                        None => built.push(LayeredRect::Leaf {
+                            rect,
+                            pixel_count: bit_img.pixel_count(rect),
+                        }),
+                    }
+                },
+                Work::Join(rect, child_count) => {
+                    let children = built.split_off(built.len() - child_count);
+                    built.push(LayeredRect::Node {
+                        rect,
+                        pixel_count: children.iter().map(|child| child.pixel_count()).sum(),
+                        children,
+                    });
+                },
             }
         }
+
+        built.pop().unwrap()
+    }
+
+    pub fn rect(&self) -> Rect {
+        match self {
+            LayeredRect::Leaf { rect, pixel_count: _ } => *rect,
+            LayeredRect::Node { rect, children: _, pixel_count: _ } => *rect,
+        }
     }
 
-    fn pixel_count(&self) -> usize {
+    /// Number of opaque pixels in `rect()`.
+    pub fn pixel_count(&self) -> usize {
         match self {
             LayeredRect::Leaf { rect: _, pixel_count } => *pixel_count,
             LayeredRect::Node { rect: _, children: _, pixel_count } => *pixel_count,
         }
     }
+
+    #[inline]
+    pub fn is_leaf(&self) -> bool {
+        matches!(self, LayeredRect::Leaf { .. })
+    }
+
+    /// Children of a node, two or four depending on `SplitStrategy`. `None` for a leaf.
+    pub fn children(&self) -> Option<&[LayeredRect]> {
+        match self {
+            LayeredRect::Leaf { rect: _, pixel_count: _ } => None,
+            LayeredRect::Node { rect: _, children, pixel_count: _ } => Some(children),
+        }
+    }
+
+    /// Deepest node containing `pos`, i.e. a leaf, or `None` if `pos` is outside of the tree.
+    /// The right and bottom edges of a rect belong to the next one.
+    pub fn node_at(&self, pos: Pos2) -> Option<&LayeredRect> {
+        let contains = |rect: Rect| rect.min.x <= pos.x && pos.x < rect.max.x && rect.min.y <= pos.y && pos.y < rect.max.y;
+        if ! contains(self.rect()) { return None; }
+        let mut node = self;
+        while let LayeredRect::Node { rect: _, children, pixel_count: _ } = node {
+            node = children.iter().find(|child| contains(child.rect()))?;
+        }
+        Some(node)
+    }
+
+    #[cfg(test)]
+    fn depth(&self) -> usize {
+        match self {
+            LayeredRect::Leaf { rect: _, pixel_count: _ } => 0,
+            LayeredRect::Node { rect: _, children, pixel_count: _ } => 1 + children.iter().map(|child| child.depth()).max().unwrap_or(0),
+        }
+    }
+}
+
+/// How `is_opaque_at`, `opaque_at_point` and `contains_pixel` treat coordinates outside of the image.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OutOfBounds {
+    /// Points outside are transparent and rects are intersected with the image.
+    #[default]
+    ReturnFalse,
+    /// Points are moved to the nearest edge pixel and rects are clamped into the image,
+    /// keeping at least one row and column.
+    Clamp,
+    /// Panics on any point or rect not fully inside of the image.
+    Panic,
 }
 
 pub struct BitImg {
     pixels: Pixels2D,
     layered_rect: LayeredRect,
+    out_of_bounds: OutOfBounds,
+    lazy: Option<LazyTree>,
 }
 
 impl BitImg {
     pub fn new(pixels: Pixels2D) -> Self {
+        Self::with_min_node_size(pixels, DEFAULT_MIN_NODE_SIZE)
+    }
+
+    /// Builds the tree with leaves of at most `min_node_size` pixels on each side, see `TreeOptions::min_node_size`.
+    ///
+    /// Panics if `min_node_size` is less than 1.0.
+    pub fn with_min_node_size(pixels: Pixels2D, min_node_size: f32) -> Self {
+        assert!(1. <= min_node_size, "min_node_size must be at least 1.0 but {}.", min_node_size);
+        Self::with_options(pixels, TreeOptions { min_node_size, ..TreeOptions::default() })
+    }
+
+    /// Builds the mask of an image without uploading a texture, e.g. for headless hit testing.
+    /// Pixels with alpha above `OPAQUE_ALPHA_THRESHOLD` are opaque, as in `Img::from_img`.
+    pub fn from_color_image(img: &ColorImage) -> Self {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(img.width() as f32, img.height() as f32));
+        BitImg::new(Pixels2D::from_bitmap(to_bitmap(img), rect))
+    }
+
+    pub fn with_options(pixels: Pixels2D, options: TreeOptions) -> Self {
+        let pixels = if options.summed_area_table && !pixels.has_summed_area_table() {
+            pixels.with_summed_area_table()
+        } else {
+            pixels
+        };
+        if options.lazy {
+            return Self {
+                layered_rect: LayeredRect::Leaf { rect: pixels.rect, pixel_count: pixels.total_opaque() },
+                lazy: Some(LazyTree { root: LazyNode::new(pixels.rect, 0, &pixels), options }),
+                pixels,
+                out_of_bounds: OutOfBounds::default(),
+            };
+        }
+
         Self {
-            layered_rect: LayeredRect::new(pixels.rect, &pixels),
+            layered_rect: LayeredRect::build(pixels.rect, &pixels, &options, 0),
             pixels,
+            out_of_bounds: OutOfBounds::default(),
+            lazy: None,
         }
     }
     
@@ -158,180 +465,4751 @@ impl BitImg {
         println!("layered_rect: {:?}", self.layered_rect);
     }
 
+    /// Renders the mask as an image, opaque pixels as `on` and the others as `off`.
+    pub fn mask_to_color_image(&self, on: Color32, off: Color32) -> ColorImage {
+        let w = self.pixels.rect.width() as usize;
+        let h = self.pixels.rect.height() as usize;
+        let mut img = ColorImage::new([w, h], off);
+        for idx in self.pixels.bits.iter() {
+            img.pixels[idx] = on;
+        }
+        img
+    }
+
     #[inline]
+    pub fn out_of_bounds(&self) -> OutOfBounds {
+        self.out_of_bounds
+    }
+
+    pub fn set_out_of_bounds(&mut self, policy: OutOfBounds) {
+        self.out_of_bounds = policy;
+    }
+
     pub fn is_opaque_at(&self, x: usize, y: usize) -> bool {
-        self.pixels.pixel_at(x, y)
+        let w = self.pixels.rect.width() as usize;
+        let h = self.pixels.rect.height() as usize;
+        if x < w && y < h { return self.pixels.pixel_at(x, y); }
+
+        match self.out_of_bounds {
+            OutOfBounds::ReturnFalse => false,
+            OutOfBounds::Clamp => w != 0 && h != 0 && self.pixels.pixel_at(x.min(w - 1), y.min(h - 1)),
+            OutOfBounds::Panic => panic!("({}, {}) is out of the image of {}x{}.", x, y, w, h),
+        }
+    }
+
+    /// Opacity at an image-local position.
+    pub fn opaque_at_point(&self, pos: Pos2) -> bool {
+        if (0. ..self.pixels.rect.width()).contains(&pos.x) && (0. ..self.pixels.rect.height()).contains(&pos.y) {
+            return self.pixels.pixel_at(pos.x as usize, pos.y as usize);
+        }
+
+        match self.out_of_bounds {
+            OutOfBounds::ReturnFalse => false,
+            OutOfBounds::Clamp => self.is_opaque_at(pos.x.max(0.) as usize, pos.y.max(0.) as usize),
+            OutOfBounds::Panic => panic!("{:?} is out of the image of {:?}.", pos, self.pixels.rect.size()),
+        }
     }
 
+    /// Same as calling `opaque_at_point` for each point. The points inside the image are sorted by the
+    /// bitmap word holding their pixel and every word is loaded once for all of its points. A word covers
+    /// 64 pixels of a row, so clustered points (a pointer trail, particles) share few loads, while
+    /// scattered points still load one word each and pay for the `O(n log n)` sort on top of that.
+    /// Points outside the image go through `opaque_at_point`.
+    pub fn opaque_at_points(&self, points: &[Pos2]) -> Vec<bool> {
+        self.opaque_at_points_counted(points).0
+    }
+
+    // Also returns the number of words loaded.
+    fn opaque_at_points_counted(&self, points: &[Pos2]) -> (Vec<bool>, usize) {
+        let w = self.pixels.rect.width();
+        let h = self.pixels.rect.height();
+        let mut result = vec![false; points.len()];
+        // Bit index of the pixel and index of the point, for the points inside the image.
+        let mut inside: Vec<(usize, usize)> = Vec::with_capacity(points.len());
+        for (i, pos) in points.iter().enumerate() {
+            if (0. ..w).contains(&pos.x) && (0. ..h).contains(&pos.y) {
+                inside.push((pos.x as usize + pos.y as usize * w as usize, i));
+            } else {
+                result[i] = self.opaque_at_point(*pos);
+            }
+        }
+        inside.sort_unstable();
+
+        let words = self.pixels.bits.words();
+        let mut loads = 0;
+        let mut start = 0;
+        while start < inside.len() {
+            let word_idx = inside[start].0 / WORD_BITS;
+            let word = words[word_idx];
+            loads += 1;
+            // The bits of one word are tested independently of each other, which leaves room for SIMD lanes.
+            let end = start + inside[start..].iter().take_while(|(bit, _)| bit / WORD_BITS == word_idx).count();
+            for (bit, i) in &inside[start..end] {
+                result[*i] = word & (1 << (bit % WORD_BITS)) != 0;
+            }
+            start = end;
+        }
+        (result, loads)
+    }
+
+    // Walks the tree with an explicit stack so that the call depth does not grow with the tree.
     fn contains_pixel_in_layer(&self, target_rect: &Rect, layered: &LayeredRect) -> bool {
-        match layered {
-            LayeredRect::Leaf { rect, pixel_count } => {
-                if *pixel_count == 0 { return false; }
-                if ! rect.intersects(*target_rect) { return false; }
-                if target_rect.contains_rect(*rect) && *pixel_count != 0 { return true; }
-                self.pixels.contains_pixel(target_rect)
+        let mut stack = vec![layered];
+        while let Some(layered) = stack.pop() {
+            let (rect, pixel_count) = (layered.rect(), layered.pixel_count());
+            if pixel_count == 0 { continue; }
+            if ! rect.intersects(*target_rect) { continue; }
+            if target_rect.contains_rect(rect) { return true; }
+            match layered {
+                LayeredRect::Leaf { rect: _, pixel_count: _ } => {
+                    if self.pixels.contains_pixel(target_rect) { return true; }
+                },
+                LayeredRect::Node { rect: _, children, pixel_count: _ } => {
+                    // Reversed so that children are visited in order.
+                    stack.extend(children.iter().rev());
+                },
+            }
+        }
+        false
+    }
+
+    pub fn contains_pixel(&self, rect: &Rect) -> bool {
+        match self.query_rect(rect) {
+            Some(covered_both) => self.contains_pixel_in(&covered_both),
+            None => false,
+        }
+    }
+
+    // Part of rect that a query looks at according to the out of bounds policy, None if it is empty.
+    fn query_rect(&self, rect: &Rect) -> Option<Rect> {
+        let covered_both = match self.out_of_bounds {
+            OutOfBounds::ReturnFalse => self.pixels.clip(rect)?,
+            OutOfBounds::Clamp => {
+                let size = self.pixels.rect.size();
+                if size.x < 1. || size.y < 1. { return None; }
+                let min = rect.min.clamp(Pos2::ZERO, (size - Vec2::new(1., 1.)).to_pos2());
+                let max = rect.max.clamp(min + Vec2::new(1., 1.), size.to_pos2());
+                Rect::from_min_max(min, max)
             },
-            LayeredRect::Node { rect, children, pixel_count } => {
-                if *pixel_count == 0 { return false; }
-                if ! rect.intersects(*target_rect) { return false; }
-                if target_rect.contains_rect(*rect) && *pixel_count != 0 { return true; }
-                if self.contains_pixel_in_layer(target_rect, &children[0]) { return true; }
-                if self.contains_pixel_in_layer(target_rect, &children[1]) { return true; }
-                return false;
+            OutOfBounds::Panic => {
+                assert!(self.pixels.rect.contains_rect(*rect), "{:?} is out of the image of {:?}.", rect, self.pixels.rect.size());
+                *rect
             },
+        };
+        if covered_both.is_positive() { Some(covered_both) } else { None }
+    }
+
+    // contains_pixel of a rect already returned by query_rect.
+    fn contains_pixel_in(&self, covered_both: &Rect) -> bool {
+        match &self.lazy {
+            Some(tree) => self.contains_pixel_in_lazy(covered_both, &tree.root, &tree.options),
+            None => self.contains_pixel_in_layer(covered_both, &self.layered_rect),
         }
     }
 
-    pub fn contains_pixel(&self, rect: &Rect) -> bool {
-        let covered_both = rect.intersect(self.pixels.rect);
-        if covered_both == Rect::NOTHING { return false; }
+    /// `contains_pixel` of each of `rects`, in the same order.
+    pub fn contains_pixel_batch(&self, rects: &[Rect]) -> Vec<bool> {
+        rects.iter().map(|rect| self.contains_pixel(rect)).collect()
+    }
+
+    fn contains_pixel_in_lazy(&self, target_rect: &Rect, node: &LazyNode, options: &TreeOptions) -> bool {
+        if node.pixel_count == 0 || ! node.rect.intersects(*target_rect) { return false; }
+        if target_rect.contains_rect(node.rect) { return true; }
+        let split = match options.split(&node.rect, node.depth) {
+            Some(split) => split,
+            None => return self.pixels.contains_pixel(&node.rect.intersect(*target_rect)),
+        };
 
-        self.contains_pixel_in_layer(&covered_both, &self.layered_rect)
+        if node.children.read().unwrap_or_else(|poisoned| poisoned.into_inner()).is_none() {
+            let mut children = node.children.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if children.is_none() {
+                *children = Some(split.iter().map(|rect| LazyNode::new(*rect, node.depth + 1, &self.pixels)).collect());
+            }
+        }
+        let children = node.children.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        children.as_ref().unwrap().iter().any(|child| self.contains_pixel_in_lazy(target_rect, child, options))
     }
-}
 
-pub struct Img {
-    texture: TextureHandle,
-    bit_img: BitImg,
-}
+    pub fn row_histogram(&self) -> Vec<usize> {
+        let w = self.pixels.rect.width() as usize;
+        let mut histogram = vec![0; self.pixels.rect.height() as usize];
+        for idx in self.pixels.bits.iter() {
+            histogram[idx / w] += 1;
+        }
+        histogram
+    }
 
-impl Img {
-    pub fn from_img<T>(name: T, img: ColorImage, ctx: &Context) -> Self where T: Into<String> {
-        let bits = to_bitset(&img);
-        let texture = ctx.load_texture(name, img, TextureOptions::LINEAR);
-        let size = texture.size();
-        let pixels = Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(size[0] as f32, size[1] as f32)));
-        let bit_img = BitImg::new(pixels);
-        Self {
-            bit_img, texture,
+    pub fn column_histogram(&self) -> Vec<usize> {
+        let w = self.pixels.rect.width() as usize;
+        let mut histogram = vec![0; w];
+        for idx in self.pixels.bits.iter() {
+            histogram[idx % w] += 1;
         }
+        histogram
     }
 
-    pub fn from_svg<T>(name: T, svg_bytes: &[u8], scale: f32, ctx: &Context) -> Result<Self, SvgError> where T: Into<String> {
-        let img = load_svg_bytes(svg_bytes, scale)?;
-        Ok(Self::from_img(name, img, ctx))
+    /// Mirrors the mask left to right.
+    pub fn flip_horizontal(&self) -> BitImg {
+        let w = self.pixels.rect.width() as usize;
+        let h = self.pixels.rect.height() as usize;
+        self.remapped(w, h, |x, y| (w - 1 - x, y))
     }
 
-    #[inline]
-    pub fn size(&self) -> Vec2 {
-        self.texture.size_vec2()
+    /// Mirrors the mask top to bottom.
+    pub fn flip_vertical(&self) -> BitImg {
+        let w = self.pixels.rect.width() as usize;
+        let h = self.pixels.rect.height() as usize;
+        self.remapped(w, h, |x, y| (x, h - 1 - y))
     }
 
-    #[inline]
-    pub fn is_opaque_at(&self, x: usize, y: usize) -> bool {
-        self.bit_img.is_opaque_at(x, y)
+    /// Rotates the mask 90 degrees clockwise (as displayed, y pointing down), swapping width and height.
+    pub fn rotate90(&self) -> BitImg {
+        let w = self.pixels.rect.width() as usize;
+        let h = self.pixels.rect.height() as usize;
+        self.remapped(h, w, |x, y| (h - 1 - y, x))
     }
 
-    #[inline]
-    pub fn contains_pixel(&self, rect: &Rect) -> bool {
-        self.bit_img.contains_pixel(rect)
+    pub fn rotate180(&self) -> BitImg {
+        let w = self.pixels.rect.width() as usize;
+        let h = self.pixels.rect.height() as usize;
+        self.remapped(w, h, |x, y| (w - 1 - x, h - 1 - y))
     }
 
-    #[inline]
-    pub fn texture_id(&self) -> TextureId {
-        self.texture.id()
+    /// Rotates the mask 270 degrees clockwise, i.e. 90 degrees counterclockwise, swapping width and height.
+    pub fn rotate270(&self) -> BitImg {
+        let w = self.pixels.rect.width() as usize;
+        let h = self.pixels.rect.height() as usize;
+        self.remapped(h, w, |x, y| (y, w - 1 - x))
     }
-}
 
-#[derive(Debug)]
-pub enum SvgError {
-    CannotParse(usvg::Error),
-    CannotLoad { width: u32, height: u32 },
-    CannotRender,
-    Other(String),
-}
+    /// Mask of the pixels inside of `rect` (truncated to whole pixels), moved to the origin.
+    /// Parts of `rect` outside of the image are transparent.
+    pub fn crop(&self, rect: &Rect) -> BitImg {
+        let w = rect.width().max(0.) as usize;
+        let h = rect.height().max(0.) as usize;
+        let left = rect.min.x.floor() as i64;
+        let top = rect.min.y.floor() as i64;
+        let mut bits = Bitmap::new(w * h);
+        let src_w = self.pixels.rect.width() as i64;
+        let src_h = self.pixels.rect.height() as i64;
+        for y in top.max(0)..(top + h as i64).min(src_h) {
+            for x in left.max(0)..(left + w as i64).min(src_w) {
+                if self.pixels.pixel_at(x as usize, y as usize) {
+                    bits.insert((x - left) as usize + (y - top) as usize * w);
+                }
+            }
+        }
 
-pub struct SvgLoader {
-    pub scale: f32,
-    pub cache: Option<LocalFileCache<Result<ColorImage, SvgError>>>,
-}
+        let cropped = Rect::from_min_size(Pos2::ZERO, Vec2::new(w as f32, h as f32));
+        let mut bit_img = BitImg::new(Pixels2D::from_bitmap(bits, cropped));
+        bit_img.out_of_bounds = self.out_of_bounds;
+        bit_img
+    }
 
-impl SvgLoader {
-    pub fn new<P>(scale: f32, cache_dir: Option<P>) -> Self where P: AsRef<Path> {
-        Self {
-            scale,
-            cache: cache_dir.and_then(|p| LocalFileCache::<Result<ColorImage, SvgError>>::new(p,
-                Box::new(|img|
-                    match img {
-                        Ok(ci) => Some(img_to_u8(ci)),
-                        Err(_) => None,
-                    }
-                ),
-                Box::new(|bin| Ok(u8_to_img(bin)))
-            )),
+    /// Resamples the mask to `new_width` x `new_height` with nearest neighbor sampling of the pixel
+    /// centers. This is approximate; re-rasterizing the SVG at the target scale gives a better mask.
+    pub fn resize(&self, new_width: usize, new_height: usize) -> BitImg {
+        let w = self.pixels.rect.width() as usize;
+        let h = self.pixels.rect.height() as usize;
+        let mut bits = Bitmap::new(new_width * new_height);
+        for y in 0..new_height {
+            let src_y = (y * 2 + 1) * h / (new_height * 2);
+            for x in 0..new_width {
+                let src_x = (x * 2 + 1) * w / (new_width * 2);
+                if self.pixels.pixel_at(src_x, src_y) {
+                    bits.insert(x + y * new_width);
+                }
+            }
         }
+
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(new_width as f32, new_height as f32));
+        let mut bit_img = BitImg::new(Pixels2D::from_bitmap(bits, rect));
+        bit_img.out_of_bounds = self.out_of_bounds;
+        bit_img
     }
 
-    pub fn load(&self, svg_bytes: &[u8]) -> Result<egui::ColorImage, SvgError> {
-        match self.cache.as_ref() {
-            Some(cache) => {
-                let mut hash = Sha256::default();
-                <u8 as Hash>::hash_slice(&self.scale.to_ne_bytes(), &mut hash);
-                <u8 as Hash>::hash_slice(svg_bytes, &mut hash);
-                let hex_str = format!("{:x}", hash.finish());
-                let fname = Path::new(&hex_str);
-                match cache.or_insert_with(fname, || load_svg_bytes(svg_bytes, self.scale)) {
-                    Ok(ok) => ok,
-                    Err(io_err) => Err(SvgError::Other(io_err.to_string()))
+    // New mask of width x height at the origin, with each opaque pixel (x, y) moved to map(x, y).
+    // The out of bounds policy is kept.
+    fn remapped<F>(&self, width: usize, height: usize, map: F) -> BitImg where F: Fn(usize, usize) -> (usize, usize) {
+        let w = self.pixels.rect.width() as usize;
+        let mut bits = Bitmap::new(width * height);
+        for idx in self.pixels.bits.iter() {
+            let (x, y) = map(idx % w, idx / w);
+            bits.insert(x + y * width);
+        }
+
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(width as f32, height as f32));
+        let mut bit_img = BitImg::new(Pixels2D::from_bitmap(bits, rect));
+        bit_img.out_of_bounds = self.out_of_bounds;
+        bit_img
+    }
+
+    /// Grows the opaque region by `radius` pixels: a pixel becomes opaque when any pixel in the square of
+    /// `2 * radius + 1` pixels around it is opaque. The size of the image is kept.
+    pub fn dilate(&self, radius: usize) -> BitImg {
+        self.morphed(radius, |count, _| 0 < count)
+    }
+
+    /// Shrinks the opaque region by `radius` pixels: a pixel stays opaque only when every pixel in the
+    /// square of `2 * radius + 1` pixels around it is opaque. Pixels outside of the image count as
+    /// transparent. `erode` followed by `dilate` (opening) removes specks, the reverse (closing) fills gaps.
+    pub fn erode(&self, radius: usize) -> BitImg {
+        self.morphed(radius, |count, window| count == window)
+    }
+
+    // Applies a square window of 2 * radius + 1 pixels as a horizontal and then a vertical pass. keep is
+    // given the number of opaque pixels in the window and the window size; parts of the window outside
+    // of the image count as transparent.
+    fn morphed<F>(&self, radius: usize, keep: F) -> BitImg where F: Fn(usize, usize) -> bool {
+        let w = self.pixels.rect.width() as usize;
+        let h = self.pixels.rect.height() as usize;
+        let window = 2 * radius + 1;
+        let pass = |src: &[bool], len: usize, stride: usize, lines: usize, line_stride: usize| {
+            let mut dst = vec![false; src.len()];
+            let mut prefix = vec![0usize; len + 1];
+            for line in 0..lines {
+                let at = |i: usize| line * line_stride + i * stride;
+                for i in 0..len {
+                    prefix[i + 1] = prefix[i] + src[at(i)] as usize;
                 }
-            },
-            None => load_svg_bytes(svg_bytes, self.scale),
+                for i in 0..len {
+                    let count = prefix[(i + radius + 1).min(len)] - prefix[i.saturating_sub(radius)];
+                    dst[at(i)] = keep(count, window);
+                }
+            }
+            dst
+        };
+        let mut grid = vec![false; w * h];
+        for idx in self.pixels.bits.iter() {
+            grid[idx] = true;
+        }
+        let grid = pass(&grid, w, 1, h, w);
+        let grid = pass(&grid, h, w, w, 1);
+
+        let mut bits = Bitmap::new(w * h);
+        for (idx, opaque) in grid.iter().enumerate() {
+            if *opaque { bits.insert(idx); }
         }
+        let mut bit_img = BitImg::new(Pixels2D::from_bitmap(bits, self.pixels.rect));
+        bit_img.out_of_bounds = self.out_of_bounds;
+        bit_img
     }
-}
 
-pub fn load_svg_bytes(svg_bytes: &[u8], scale: f32) -> Result<egui::ColorImage, SvgError> {
-    let opt = usvg::Options::default();
-    let usvg_tree: usvg::Tree = usvg::Tree::from_data(svg_bytes, &opt).map_err(|err: usvg::Error| SvgError::CannotParse(err))?;
-    let size = usvg_tree.size();
-    let w = size.width().ceil() as usize;
-    let h = size.height().ceil() as usize;
+    /// Places the mask centered on a transparent canvas of the given size, so that masks of different
+    /// sizes share one coordinate space. An odd margin puts the extra pixel right/bottom.
+    /// The out of bounds policy is kept. Returns `None` if the canvas is smaller than the mask.
+    pub fn centered_on_canvas(&self, canvas: Vec2) -> Option<BitImg> {
+        let w = self.pixels.rect.width() as usize;
+        let h = self.pixels.rect.height() as usize;
+        let canvas_w = canvas.x as usize;
+        let canvas_h = canvas.y as usize;
+        if canvas.x < 0. || canvas.y < 0. || canvas_w < w || canvas_h < h { return None; }
 
-    let mut pixmap = resvg::tiny_skia::Pixmap::new(((w as f32) * scale) as u32, ((h as f32) * scale) as u32)
-        .ok_or_else(|| SvgError::CannotLoad { width: w as u32, height: h as u32})?;
-    resvg::render(&usvg_tree, usvg::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+        let offset_x = (canvas_w - w) / 2;
+        let offset_y = (canvas_h - h) / 2;
+        let mut bits = Bitmap::new(canvas_w * canvas_h);
+        for idx in self.pixels.bits.iter() {
+            bits.insert((idx % w + offset_x) + (idx / w + offset_y) * canvas_w);
+        }
 
-    let img = egui::ColorImage::from_rgba_unmultiplied(
-        [pixmap.width() as usize, pixmap.height() as usize], pixmap.data(),
-    );
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(canvas_w as f32, canvas_h as f32));
+        let mut bit_img = BitImg::new(Pixels2D::from_bitmap(bits, rect));
+        bit_img.out_of_bounds = self.out_of_bounds;
+        Some(bit_img)
+    }
 
-    Ok(img)
-}
+    /// Bounding boxes of the transparent regions that are not connected to the image border,
+    /// e.g. the inside of an 'O'. Transparent pixels are connected according to `connectivity`.
+    pub fn holes(&self, connectivity: Connectivity) -> Vec<Rect> {
+        let w = self.pixels.rect.width() as usize;
+        let h = self.pixels.rect.height() as usize;
+        find_regions(w, h, |x, y| !self.pixels.pixel_at(x, y), connectivity).into_iter()
+            .filter(|region| !region.touches_border)
+            .map(|region| region.bounds)
+            .collect()
+    }
 
-pub fn to_bitset(img: &ColorImage) -> BitSet {
-    let w = img.width();
-    let h = img.height();
-    let mut bitset = BitSet::with_capacity(w * h);
-    for y in 0..h {
-        for x in 0..w {
-            if img[(x, y)] != Color32::TRANSPARENT {
-                bitset.insert(w * y + x);
+    /// Connected groups of opaque pixels, ordered by their first pixel in scan order. Opaque pixels
+    /// are connected according to `connectivity`.
+    pub fn connected_components(&self, connectivity: Connectivity) -> Vec<Component> {
+        let w = self.pixels.rect.width() as usize;
+        let h = self.pixels.rect.height() as usize;
+        find_regions(w, h, |x, y| self.pixels.pixel_at(x, y), connectivity).into_iter()
+            .map(|region| Component { pixel_count: region.pixel_count, bounds: region.bounds })
+            .collect()
+    }
+
+    // The tree built up front, None for a lazy tree whose nodes only `contains_pixel` sees.
+    fn eager_tree(&self) -> Option<&LayeredRect> {
+        match self.lazy {
+            Some(_) => None,
+            None => Some(&self.layered_rect),
+        }
+    }
+
+    /// Graphviz DOT representation of the tree. Nodes are ellipses, leaves are boxes filled when
+    /// they contain opaque pixels. `None` with `TreeOptions::lazy`.
+    pub fn to_dot(&self) -> Option<String> {
+        fn write_node(layered: &LayeredRect, next_id: &mut usize, out: &mut String) -> usize {
+            let id = *next_id;
+            *next_id += 1;
+            let (rect, pixel_count) = match layered {
+                LayeredRect::Leaf { rect, pixel_count } => (rect, pixel_count),
+                LayeredRect::Node { rect, children: _, pixel_count } => (rect, pixel_count),
+            };
+            let label = format!("({}, {}) {}x{}\\npixel_count={}", rect.min.x, rect.min.y, rect.width(), rect.height(), pixel_count);
+            match layered {
+                LayeredRect::Leaf { rect: _, pixel_count } => {
+                    let fill = if *pixel_count == 0 { "white" } else { "gray" };
+                    out.push_str(&format!("    n{} [label=\"{}\", shape=box, style=filled, fillcolor={}];\n", id, label, fill));
+                },
+                LayeredRect::Node { rect: _, children, pixel_count: _ } => {
+                    out.push_str(&format!("    n{} [label=\"{}\", shape=ellipse];\n", id, label));
+                    for child in children.iter() {
+                        let child_id = write_node(child, next_id, out);
+                        out.push_str(&format!("    n{} -> n{};\n", id, child_id));
+                    }
+                },
             }
+            id
         }
+
+        let tree = self.eager_tree()?;
+        let mut out = String::from("digraph LayeredRect {\n");
+        write_node(tree, &mut 0, &mut out);
+        out.push_str("}\n");
+        Some(out)
     }
 
-    bitset
-}
+    /// SVG drawing one rect per node of the tree, parents before their children, filled black with an
+    /// opacity of the ratio of opaque pixels in the node. `None` with `TreeOptions::lazy`.
+    pub fn tree_to_svg(&self) -> Option<String> {
+        fn write_node(layered: &LayeredRect, out: &mut String) {
+            let (rect, pixel_count) = match layered {
+                LayeredRect::Leaf { rect, pixel_count } => (rect, pixel_count),
+                LayeredRect::Node { rect, children: _, pixel_count } => (rect, pixel_count),
+            };
+            let area = rect.width() * rect.height();
+            let density = if 0. < area { *pixel_count as f32 / area } else { 0. };
+            out.push_str(&format!(
+                "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"black\" fill-opacity=\"{}\" stroke=\"red\" stroke-width=\"0.1\"/>\n",
+                rect.min.x, rect.min.y, rect.width(), rect.height(), density
+            ));
+            if let LayeredRect::Node { rect: _, children, pixel_count: _ } = layered {
+                for child in children {
+                    write_node(child, out);
+                }
+            }
+        }
 
-#[cfg(test)]
-mod tests {
-    use std::io::ErrorKind;
-    use bit_set::BitSet;
-    use egui::{ColorImage, Color32, Context, Rect, Pos2, Vec2};
-    use local_file_cache::LocalFileCache;
-    use crate::{Img, Pixels2D, LayeredRect, load_svg_bytes, SvgLoader};
+        let tree = self.eager_tree()?;
+        let size = self.pixels.rect.size();
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+            size.x, size.y, size.x, size.y
+        );
+        write_node(tree, &mut out);
+        out.push_str("</svg>\n");
+        Some(out)
+    }
 
-    use super::to_bitset;
+    /// Aggregates of the tree, for tuning `TreeOptions`. `None` with `TreeOptions::lazy`.
+    pub fn tree_stats(&self) -> Option<TreeStats> {
+        fn walk(layered: &LayeredRect, depth: usize, stats: &mut TreeStats) {
+            stats.node_count += 1;
+            stats.depth = stats.depth.max(depth);
+            match layered {
+                LayeredRect::Leaf { rect: _, pixel_count } => {
+                    stats.leaf_count += 1;
+                    if *pixel_count == 0 { stats.empty_leaf_count += 1; }
+                    stats.min_leaf_pixels = if stats.leaf_count == 1 { *pixel_count } else { stats.min_leaf_pixels.min(*pixel_count) };
+                    stats.max_leaf_pixels = stats.max_leaf_pixels.max(*pixel_count);
+                },
+                LayeredRect::Node { rect: _, children, pixel_count: _ } => {
+                    for child in children {
+                        walk(child, depth + 1, stats);
+                    }
+                },
+            }
+        }
 
-    const T: Color32 = Color32::TRANSPARENT;
-    const B: Color32 = Color32::BLACK;
+        let tree = self.eager_tree()?;
+        let mut stats = TreeStats::default();
+        walk(tree, 0, &mut stats);
+        stats.mean_leaf_pixels = tree.pixel_count() as f32 / stats.leaf_count as f32;
+        Some(stats)
+    }
 
-    #[test]
-    fn img_can_convert_to_bitset() {
+    /// Rects of every leaf of the tree that intersects `rect`, regardless of their opacity.
+    /// `None` with `TreeOptions::lazy`.
+    pub fn leaves_intersecting(&self, rect: &Rect) -> Option<Vec<Rect>> {
+        let tree = self.eager_tree()?;
+        let mut leaves = Vec::new();
+        collect_leaves_intersecting(rect, tree, &mut leaves);
+        Some(leaves)
+    }
+
+    /// Estimates edge coverage at (x, y) as the fraction of opaque pixels in its 3x3 neighborhood
+    /// (neighbors outside the image are not counted). Interior pixels yield 1.0 and pixels far from
+    /// any opaque pixel 0.0. This is only a heuristic: the mask keeps a single bit per pixel, so the
+    /// rendered alpha is not available.
+    pub fn coverage_at(&self, x: usize, y: usize) -> f32 {
+        let w = self.pixels.rect.width() as usize;
+        let h = self.pixels.rect.height() as usize;
+        if w <= x || h <= y { return 0.; }
+
+        let mut total: usize = 0;
+        let mut opaque: usize = 0;
+        for ny in y.saturating_sub(1)..=(y + 1).min(h - 1) {
+            for nx in x.saturating_sub(1)..=(x + 1).min(w - 1) {
+                total += 1;
+                if self.pixels.pixel_at(nx, ny) { opaque += 1; }
+            }
+        }
+
+        opaque as f32 / total as f32
+    }
+
+    /// Like `contains_pixel`, but only counts opaque pixels having at least `min_neighbors` opaque
+    /// 8-neighbors, so that isolated specks such as anti-aliasing halos do not register.
+    pub fn contains_solid_pixel(&self, rect: &Rect, min_neighbors: usize) -> bool {
+        let covered_both = match self.query_rect(rect) {
+            Some(covered_both) => covered_both,
+            None => return false,
+        };
+        if ! self.contains_pixel_in(&covered_both) { return false; }
+        let w = self.pixels.rect.width() as usize;
+        let h = self.pixels.rect.height() as usize;
+        let start_x = covered_both.min.x as usize;
+        let start_y = covered_both.min.y as usize;
+
+        for y in start_y..(start_y + covered_both.height() as usize) {
+            for x in start_x..(start_x + covered_both.width() as usize) {
+                if ! self.pixels.pixel_at(x, y) { continue; }
+                let mut neighbors: usize = 0;
+                for ny in y.saturating_sub(1)..=(y + 1).min(h - 1) {
+                    for nx in x.saturating_sub(1)..=(x + 1).min(w - 1) {
+                        if (nx, ny) != (x, y) && self.pixels.pixel_at(nx, ny) { neighbors += 1; }
+                    }
+                }
+                if min_neighbors <= neighbors { return true; }
+            }
+        }
+
+        false
+    }
+
+    /// Whether any opaque pixel center lies within `radius` of the segment `a`-`b`. With `a == b` this
+    /// is a circle test. Subtrees without opaque pixels or too far from the segment are skipped.
+    pub fn capsule_hits_opaque(&self, a: Pos2, b: Pos2, radius: f32) -> bool {
+        let bounds = Rect::from_two_pos(a, b).expand(radius);
+        let bounds = Rect::from_min_max(bounds.min.floor(), bounds.max.floor() + Vec2::new(1., 1.)).intersect(self.pixels.rect);
+        if bounds.width() <= 0. || bounds.height() <= 0. { return false; }
+
+        self.capsule_hits_in_layer(a, b, radius, &bounds, &self.layered_rect)
+    }
+
+    /// Whether any opaque pixel center lies within the disc. Same as `capsule_hits_opaque(center, center, radius)`.
+    pub fn contains_pixel_in_circle(&self, center: Pos2, radius: f32) -> bool {
+        if radius < 0. { return false; }
+        self.capsule_hits_opaque(center, center, radius)
+    }
+
+    fn capsule_hits_in_layer(&self, a: Pos2, b: Pos2, radius: f32, bounds: &Rect, layered: &LayeredRect) -> bool {
+        match layered {
+            LayeredRect::Leaf { rect, pixel_count } => {
+                if *pixel_count == 0 || ! rect.intersects(*bounds) { return false; }
+                let target = rect.intersect(*bounds);
+                let start_x = target.min.x as usize;
+                let start_y = target.min.y as usize;
+                for y in start_y..(target.max.y.ceil() as usize) {
+                    for x in start_x..(target.max.x.ceil() as usize) {
+                        let center = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+                        if self.pixels.pixel_at(x, y) && distance_to_segment(center, a, b) <= radius { return true; }
+                    }
+                }
+                false
+            },
+            LayeredRect::Node { rect, children, pixel_count } => {
+                if *pixel_count == 0 || ! rect.intersects(*bounds) { return false; }
+                let half_diagonal = rect.size().length() / 2.;
+                if radius + half_diagonal < distance_to_segment(rect.center(), a, b) { return false; }
+                children.iter().any(|child| self.capsule_hits_in_layer(a, b, radius, bounds, child))
+            },
+        }
+    }
+
+    /// Whether the segment `a`-`b` passes through any opaque pixel. The pixels are visited in order along
+    /// the segment (grid traversal), stopping at the first opaque one.
+    pub fn intersects_segment(&self, a: Pos2, b: Pos2) -> bool {
+        let bounds = Rect::from_two_pos(a, b);
+        let bounds = Rect::from_min_max(bounds.min.floor(), bounds.max.floor() + Vec2::new(1., 1.)).intersect(self.pixels.rect);
+        if bounds.width() <= 0. || bounds.height() <= 0. || ! self.contains_pixel_in_layer(&bounds, &self.layered_rect) { return false; }
+
+        // Clip the segment into the image (Liang-Barsky) so that long segments only walk the pixels inside.
+        let d = b - a;
+        let (mut t0, mut t1) = (0f32, 1f32);
+        for (p, q) in [(-d.x, a.x), (d.x, self.pixels.rect.width() - a.x), (-d.y, a.y), (d.y, self.pixels.rect.height() - a.y)] {
+            if p == 0. {
+                if q < 0. { return false; }
+            } else if p < 0. {
+                t0 = t0.max(q / p);
+            } else {
+                t1 = t1.min(q / p);
+            }
+        }
+        if t1 < t0 { return false; }
+        let start = a + d * t0;
+        let end = a + d * t1;
+
+        let w = self.pixels.rect.width() as i64;
+        let h = self.pixels.rect.height() as i64;
+        let clamp_cell = |v: f32, len: i64| (v.floor() as i64).clamp(0, len - 1);
+        let (mut x, mut y) = (clamp_cell(start.x, w), clamp_cell(start.y, h));
+        let (end_x, end_y) = (clamp_cell(end.x, w), clamp_cell(end.y, h));
+        let step_x = if 0. < d.x { 1 } else { -1 };
+        let step_y = if 0. < d.y { 1 } else { -1 };
+        let next_boundary = |cell: i64, step: i64| (cell + if 0 < step { 1 } else { 0 }) as f32;
+        let mut t_max_x = if d.x == 0. { f32::INFINITY } else { (next_boundary(x, step_x) - start.x) / d.x };
+        let mut t_max_y = if d.y == 0. { f32::INFINITY } else { (next_boundary(y, step_y) - start.y) / d.y };
+        let t_delta_x = if d.x == 0. { f32::INFINITY } else { 1. / d.x.abs() };
+        let t_delta_y = if d.y == 0. { f32::INFINITY } else { 1. / d.y.abs() };
+
+        for _ in 0..=((end_x - x).abs() + (end_y - y).abs()) {
+            if self.pixels.pixel_at(x as usize, y as usize) { return true; }
+            if t_max_x < t_max_y {
+                x += step_x;
+                t_max_x += t_delta_x;
+            } else {
+                y += step_y;
+                t_max_y += t_delta_y;
+            }
+            if x < 0 || y < 0 || w <= x || h <= y { break; }
+        }
+
+        false
+    }
+
+    /// Whether any opaque pixel center lies inside of the polygon, using the even-odd rule so that
+    /// self-intersecting outlines behave consistently. Fewer than three points never hit.
+    pub fn contains_pixel_in_polygon(&self, points: &[Pos2]) -> bool {
+        if points.len() < 3 { return false; }
+        let bounds = Rect::from_points(points);
+        let bounds = Rect::from_min_max(bounds.min.floor(), bounds.max.floor() + Vec2::new(1., 1.)).intersect(self.pixels.rect);
+        if bounds.width() <= 0. || bounds.height() <= 0. { return false; }
+
+        self.polygon_hits_in_layer(points, &bounds, &self.layered_rect)
+    }
+
+    fn polygon_hits_in_layer(&self, points: &[Pos2], bounds: &Rect, layered: &LayeredRect) -> bool {
+        match layered {
+            LayeredRect::Leaf { rect, pixel_count } => {
+                if *pixel_count == 0 || ! rect.intersects(*bounds) { return false; }
+                let target = rect.intersect(*bounds);
+                let start_x = target.min.x as usize;
+                let start_y = target.min.y as usize;
+                for y in start_y..(target.max.y.ceil() as usize) {
+                    for x in start_x..(target.max.x.ceil() as usize) {
+                        let center = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+                        if self.pixels.pixel_at(x, y) && point_in_polygon(center, points) { return true; }
+                    }
+                }
+                false
+            },
+            LayeredRect::Node { rect, children, pixel_count } => {
+                if *pixel_count == 0 || ! rect.intersects(*bounds) { return false; }
+                children.iter().any(|child| self.polygon_hits_in_layer(points, bounds, child))
+            },
+        }
+    }
+
+    /// `contains_pixel` with an explicit rule for pixels only partially covered by `rect`.
+    pub fn contains_pixel_coverage(&self, rect: &Rect, mode: CoverageMode) -> bool {
+        let (min, max) = match mode {
+            CoverageMode::AnyOverlap => (
+                rect.min.floor(),
+                rect.max.ceil().max(rect.min.floor() + Vec2::new(1., 1.)),
+            ),
+            CoverageMode::CenterInside => (
+                (rect.min - Vec2::new(0.5, 0.5)).ceil(),
+                (rect.max - Vec2::new(0.5, 0.5)).ceil(),
+            ),
+        };
+        let pixel_rect = Rect::from_min_max(min.max(Pos2::ZERO), max);
+        if pixel_rect.width() <= 0. || pixel_rect.height() <= 0. { return false; }
+
+        self.contains_pixel(&pixel_rect)
+    }
+
+    /// Number of opaque pixels, read from the root of the tree.
+    #[inline]
+    pub fn total_opaque(&self) -> usize {
+        self.layered_rect.pixel_count()
+    }
+
+    /// `(x, y)` of each opaque pixel in scan order (row by row).
+    pub fn opaque_pixels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let w = self.pixels.rect.width() as usize;
+        self.pixels.bits.iter().map(move |idx| (idx % w, idx / w))
+    }
+
+    /// Whether every pixel is opaque, from the count at the root of the tree. False for an empty image.
+    pub fn is_fully_opaque(&self) -> bool {
+        let area = (self.pixels.rect.width() as usize) * (self.pixels.rect.height() as usize);
+        0 < area && self.total_opaque() == area
+    }
+
+    /// Whether no pixel is opaque, from the count at the root of the tree.
+    #[inline]
+    pub fn is_fully_transparent(&self) -> bool {
+        self.total_opaque() == 0
+    }
+
+    /// Root of the tree, for custom traversals. `None` with `TreeOptions::lazy`, whose nodes are built
+    /// on demand and are not exposed.
+    #[inline]
+    pub fn layered_rect(&self) -> Option<&LayeredRect> {
+        self.eager_tree()
+    }
+
+    /// Fraction of opaque pixels in the part of `rect` inside of the image, 0.0 if they do not intersect.
+    pub fn opaque_ratio(&self, rect: &Rect) -> f32 {
+        let clipped = match self.pixels.clip(rect) {
+            Some(clipped) => clipped,
+            None => return 0.,
+        };
+        let area = (clipped.width() as usize) * (clipped.height() as usize);
+        if area == 0 { return 0.; }
+
+        (self.pixels.pixel_count(clipped) as f32 / area as f32).clamp(0., 1.)
+    }
+
+    /// Top-left-most opaque pixel in `rect` in scan order (row by row), or `None`.
+    pub fn first_opaque_in(&self, rect: &Rect) -> Option<Pos2> {
+        let target = self.pixels.clip(rect)?;
+        let mut first: Option<(usize, usize)> = None;
+        self.first_opaque_in_layer(&target, &self.layered_rect, &mut first);
+        first.map(|(x, y)| Pos2::new(x as f32, y as f32))
+    }
+
+    fn first_opaque_in_layer(&self, target_rect: &Rect, layered: &LayeredRect, first: &mut Option<(usize, usize)>) {
+        match layered {
+            LayeredRect::Leaf { rect, pixel_count } => {
+                if *pixel_count == 0 || ! rect.intersects(*target_rect) { return; }
+                let covered = rect.intersect(*target_rect);
+                let start_x = covered.min.x as usize;
+                let start_y = covered.min.y as usize;
+                for y in start_y..(start_y + covered.height() as usize) {
+                    if first.map_or(false, |(_, first_y)| first_y < y) { return; }
+                    for x in start_x..(start_x + covered.width() as usize) {
+                        if self.pixels.pixel_at(x, y) {
+                            if first.map_or(true, |(first_x, first_y)| (y, x) < (first_y, first_x)) { *first = Some((x, y)); }
+                            return;
+                        }
+                    }
+                }
+            },
+            LayeredRect::Node { rect, children, pixel_count } => {
+                if *pixel_count == 0 || ! rect.intersects(*target_rect) { return; }
+                // Nothing below the current candidate row can come first.
+                if first.map_or(false, |(_, first_y)| (first_y as f32) < rect.min.y.max(target_rect.min.y)) { return; }
+                for child in children {
+                    self.first_opaque_in_layer(target_rect, child, first);
+                }
+            },
+        }
+    }
+
+    /// Opaque pixel whose center is closest to `from` and no farther than `max_radius`, or `None`.
+    /// Subtrees without opaque pixels or farther than the best candidate so far are skipped.
+    pub fn nearest_opaque(&self, from: Pos2, max_radius: f32) -> Option<Pos2> {
+        if max_radius < 0. { return None; }
+        let mut best: Option<((usize, usize), f32)> = None;
+        self.nearest_opaque_in_layer(from, max_radius * max_radius, &self.layered_rect, &mut best);
+        best.map(|((x, y), _)| Pos2::new(x as f32, y as f32))
+    }
+
+    fn nearest_opaque_in_layer(&self, from: Pos2, max_dist_sq: f32, layered: &LayeredRect, best: &mut Option<((usize, usize), f32)>) {
+        let limit = best.map_or(max_dist_sq, |(_, dist_sq)| dist_sq);
+        match layered {
+            LayeredRect::Leaf { rect, pixel_count } => {
+                if *pixel_count == 0 || limit < rect.distance_sq_to_pos(from) { return; }
+                let start_x = rect.min.x as usize;
+                let start_y = rect.min.y as usize;
+                for y in start_y..(start_y + rect.height() as usize) {
+                    for x in start_x..(start_x + rect.width() as usize) {
+                        if ! self.pixels.pixel_at(x, y) { continue; }
+                        let dist_sq = from.distance_sq(Pos2::new(x as f32 + 0.5, y as f32 + 0.5));
+                        if best.map_or(dist_sq <= max_dist_sq, |(_, best_sq)| dist_sq < best_sq) {
+                            *best = Some(((x, y), dist_sq));
+                        }
+                    }
+                }
+            },
+            LayeredRect::Node { rect, children, pixel_count } => {
+                if *pixel_count == 0 || limit < rect.distance_sq_to_pos(from) { return; }
+                // Visiting the closer children first tightens the bound early.
+                let mut ordered: Vec<&LayeredRect> = children.iter().collect();
+                ordered.sort_by(|a, b| a.rect().distance_sq_to_pos(from).total_cmp(&b.rect().distance_sq_to_pos(from)));
+                for child in ordered {
+                    self.nearest_opaque_in_layer(from, max_dist_sq, child, best);
+                }
+            },
+        }
+    }
+
+    /// Smallest rect containing every opaque pixel, or `None` if the image is fully transparent.
+    pub fn opaque_bounds(&self) -> Option<Rect> {
+        let mut bounds: Option<Rect> = None;
+        self.opaque_bounds_in_layer(&self.layered_rect, &mut bounds);
+        bounds
+    }
+
+    fn opaque_bounds_in_layer(&self, layered: &LayeredRect, bounds: &mut Option<Rect>) {
+        match layered {
+            LayeredRect::Leaf { rect, pixel_count } => {
+                if *pixel_count == 0 || bounds.map_or(false, |b| b.contains_rect(*rect)) { return; }
+                let start_x = rect.min.x as usize;
+                let start_y = rect.min.y as usize;
+                for y in start_y..(start_y + rect.height() as usize) {
+                    for x in start_x..(start_x + rect.width() as usize) {
+                        if self.pixels.pixel_at(x, y) {
+                            let pixel = Rect::from_min_size(Pos2::new(x as f32, y as f32), Vec2::new(1., 1.));
+                            *bounds = Some(bounds.map_or(pixel, |b| b.union(pixel)));
+                        }
+                    }
+                }
+            },
+            LayeredRect::Node { rect, children, pixel_count } => {
+                if *pixel_count == 0 || bounds.map_or(false, |b| b.contains_rect(*rect)) { return; }
+                for child in children {
+                    self.opaque_bounds_in_layer(child, bounds);
+                }
+            },
+        }
+    }
+
+    /// Counts opaque pixels in `rect` but stops as soon as `cap` is reached, i.e. returns `min(count, cap)`.
+    /// Parts of `rect` outside of the image are handled by the `OutOfBounds` policy as in `contains_pixel`.
+    pub fn opaque_count_in_capped(&self, rect: &Rect, cap: usize) -> usize {
+        let covered_both = match self.query_rect(rect) {
+            Some(covered_both) => covered_both,
+            None => return 0,
+        };
+        if cap == 0 { return 0; }
+
+        let mut count: usize = 0;
+        self.count_in_layer_capped(&covered_both, &self.layered_rect, cap, &mut count);
+        count.min(cap)
+    }
+
+    fn count_in_layer_capped(&self, target_rect: &Rect, layered: &LayeredRect, cap: usize, count: &mut usize) {
+        if cap <= *count { return; }
+        match layered {
+            LayeredRect::Leaf { rect, pixel_count } => {
+                if *pixel_count == 0 || ! rect.intersects(*target_rect) { return; }
+                if target_rect.contains_rect(*rect) {
+                    *count += *pixel_count;
+                } else {
+                    *count += self.pixels.pixel_count(rect.intersect(*target_rect));
+                }
+            },
+            LayeredRect::Node { rect, children, pixel_count } => {
+                if *pixel_count == 0 || ! rect.intersects(*target_rect) { return; }
+                if target_rect.contains_rect(*rect) {
+                    *count += *pixel_count;
+                } else {
+                    for child in children {
+                        self.count_in_layer_capped(target_rect, child, cap, count);
+                    }
+                }
+            },
+        }
+    }
+
+    /// Mask with every pixel of the image flipped, so that transparent pixels become opaque and vice versa.
+    pub fn invert(&self) -> BitImg {
+        let mut bit_img = BitImg::new(Pixels2D::from_bitmap(self.pixels.bits.complement(), self.pixels.rect));
+        bit_img.out_of_bounds = self.out_of_bounds;
+        bit_img
+    }
+
+    /// Pixels opaque in either mask, `None` if the dimensions differ. The result keeps the out of bounds
+    /// policy of `self`.
+    pub fn union(&self, other: &BitImg) -> Option<BitImg> {
+        if self.pixels.rect.size() != other.pixels.rect.size() { return None; }
+        let mut bit_img = BitImg::new(Pixels2D::from_bitmap(self.pixels.bits.union(&other.pixels.bits), self.pixels.rect));
+        bit_img.out_of_bounds = self.out_of_bounds;
+        Some(bit_img)
+    }
+
+    /// Pixels opaque in both masks, `None` if the dimensions differ. The result keeps the out of bounds
+    /// policy of `self`.
+    pub fn intersect(&self, other: &BitImg) -> Option<BitImg> {
+        if self.pixels.rect.size() != other.pixels.rect.size() { return None; }
+        let mut bit_img = BitImg::new(Pixels2D::from_bitmap(self.pixels.bits.intersection(&other.pixels.bits), self.pixels.rect));
+        bit_img.out_of_bounds = self.out_of_bounds;
+        Some(bit_img)
+    }
+
+    /// Intersection over union of the opaque pixels of two masks of the same size.
+    /// Two fully transparent masks are considered identical and yield 1.0.
+    ///
+    /// Panics if the dimensions differ.
+    pub fn iou(&self, other: &BitImg) -> f32 {
+        assert_eq!(self.pixels.rect.size(), other.pixels.rect.size(), "iou() requires masks of the same size.");
+
+        let union = self.pixels.bits.union_count(&other.pixels.bits);
+        if union == 0 { return 1.0; }
+        let intersection = self.pixels.bits.intersection_count(&other.pixels.bits);
+
+        intersection as f32 / union as f32
+    }
+}
+
+// egui::Rect is not serializable without the serde feature of egui, so the rect is stored as raw floats.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Pixels2DRepr {
+    min: [f32; 2],
+    size: [f32; 2],
+    bits: Vec<u64>,
+    #[serde(default)]
+    summed_area_table: bool,
+}
+
+#[cfg(feature = "serde")]
+impl From<Pixels2D> for Pixels2DRepr {
+    fn from(pixels: Pixels2D) -> Self {
+        Self {
+            min: [pixels.rect.min.x, pixels.rect.min.y],
+            size: [pixels.rect.width(), pixels.rect.height()],
+            bits: pixels.bits.words().to_vec(),
+            summed_area_table: pixels.has_summed_area_table(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<Pixels2DRepr> for Pixels2D {
+    fn from(repr: Pixels2DRepr) -> Self {
+        let rect = Rect::from_min_size(Pos2::new(repr.min[0], repr.min[1]), Vec2::new(repr.size[0], repr.size[1]));
+        let len = (rect.width() as usize) * (rect.height() as usize);
+        let pixels = Pixels2D::from_bitmap(Bitmap::from_words(repr.bits, len), rect);
+        if repr.summed_area_table { pixels.with_summed_area_table() } else { pixels }
+    }
+}
+
+/// Only the pixels and the out of bounds policy are stored. The tree is rebuilt with the default
+/// `TreeOptions` on deserialization.
+#[cfg(feature = "serde")]
+impl serde::Serialize for BitImg {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("BitImg", 2)?;
+        state.serialize_field("pixels", &self.pixels)?;
+        state.serialize_field("out_of_bounds", &self.out_of_bounds)?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for BitImg {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: serde::Deserializer<'de> {
+        #[derive(serde::Deserialize)]
+        struct BitImgRepr {
+            pixels: Pixels2D,
+            out_of_bounds: OutOfBounds,
+        }
+
+        let repr = BitImgRepr::deserialize(deserializer)?;
+        let mut bit_img = BitImg::new(repr.pixels);
+        bit_img.set_out_of_bounds(repr.out_of_bounds);
+        Ok(bit_img)
+    }
+}
+
+/// Returned by `BitImg::tree_stats`.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct TreeStats {
+    /// Depth of the deepest leaf, 0 if the root is a leaf.
+    pub depth: usize,
+    /// Number of nodes including the leaves.
+    pub node_count: usize,
+    pub leaf_count: usize,
+    /// Leaves without any opaque pixel.
+    pub empty_leaf_count: usize,
+    pub min_leaf_pixels: usize,
+    pub max_leaf_pixels: usize,
+    pub mean_leaf_pixels: f32,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Connectivity {
+    /// Pixels sharing an edge are connected.
+    Four,
+    /// Pixels sharing an edge or a corner are connected.
+    Eight,
+}
+
+impl Connectivity {
+    fn offsets(self) -> &'static [(isize, isize)] {
+        match self {
+            Connectivity::Four => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+            Connectivity::Eight => &[(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)],
+        }
+    }
+}
+
+/// Connected opaque pixels, as found by `BitImg::connected_components`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Component {
+    pub pixel_count: usize,
+    pub bounds: Rect,
+}
+
+struct Region {
+    bounds: Rect,
+    pixel_count: usize,
+    touches_border: bool,
+}
+
+// Labels the connected regions of the pixels for which is_member returns true, in scan order.
+fn find_regions<F>(w: usize, h: usize, is_member: F, connectivity: Connectivity) -> Vec<Region> where F: Fn(usize, usize) -> bool {
+    let mut visited = vec![false; w * h];
+    let mut regions = Vec::new();
+    let mut stack = Vec::new();
+
+    for start in 0..(w * h) {
+        if visited[start] || !is_member(start % w, start / w) { continue; }
+        visited[start] = true;
+        stack.push(start);
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (w, h, 0, 0);
+        let mut touches_border = false;
+        let mut pixel_count = 0;
+
+        while let Some(idx) = stack.pop() {
+            let (x, y) = (idx % w, idx / w);
+            pixel_count += 1;
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+            if x == 0 || y == 0 || x == w - 1 || y == h - 1 { touches_border = true; }
+
+            for (dx, dy) in connectivity.offsets() {
+                let nx = x as isize + dx;
+                let ny = y as isize + dy;
+                if nx < 0 || ny < 0 || w as isize <= nx || h as isize <= ny { continue; }
+                let (nx, ny) = (nx as usize, ny as usize);
+                let n = nx + ny * w;
+                if !visited[n] && is_member(nx, ny) {
+                    visited[n] = true;
+                    stack.push(n);
+                }
+            }
+        }
+
+        regions.push(Region {
+            bounds: Rect::from_min_max(Pos2::new(min_x as f32, min_y as f32), Pos2::new((max_x + 1) as f32, (max_y + 1) as f32)),
+            pixel_count,
+            touches_border,
+        });
+    }
+
+    regions
+}
+
+fn distance_to_segment(p: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let ab = b - a;
+    let len_sq = ab.length_sq();
+    if len_sq == 0. { return p.distance(a); }
+    let t = ((p - a).dot(ab) / len_sq).clamp(0., 1.);
+    p.distance(a + ab * t)
+}
+
+// Even-odd rule: counts the edges crossed by a ray cast from p to the right.
+fn point_in_polygon(p: Pos2, points: &[Pos2]) -> bool {
+    let mut inside = false;
+    let mut j = points.len() - 1;
+    for i in 0..points.len() {
+        let (a, b) = (points[i], points[j]);
+        if (a.y > p.y) != (b.y > p.y) && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+fn collect_leaves_intersecting(target_rect: &Rect, layered: &LayeredRect, leaves: &mut Vec<Rect>) {
+    match layered {
+        LayeredRect::Leaf { rect, pixel_count: _ } => {
+            // The only empty leaf is the root of an image without pixels.
+            if rect.is_positive() && rect.intersects(*target_rect) { leaves.push(*rect); }
+        },
+        LayeredRect::Node { rect, children, pixel_count: _ } => {
+            if ! rect.intersects(*target_rect) { return; }
+            for child in children {
+                collect_leaves_intersecting(target_rect, child, leaves);
+            }
+        },
+    }
+}
+
+/// How pixels partially covered by a query rect are treated.
+///
+/// `contains_pixel` truncates the rect to whole pixels (`floor(min)` for `trunc(size)` pixels),
+/// which agrees with both modes for rects whose edges lie on pixel boundaries.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum CoverageMode {
+    /// Every pixel the rect overlaps at all.
+    AnyOverlap,
+    /// Only pixels whose center lies within the rect (min inclusive, max exclusive).
+    CenterInside,
+}
+
+pub struct Img {
+    name: String,
+    texture: TextureHandle,
+    bit_img: BitImg,
+    color_image: Option<ColorImage>,
+}
+
+impl Img {
+    pub fn from_img<T>(name: T, img: ColorImage, ctx: &Context) -> Self where T: Into<String> {
+        Self::from_img_with_options(name, img, TextureOptions::LINEAR, ctx)
+    }
+
+    /// Same as `from_img` but with explicit texture filtering/wrapping, e.g. `TextureOptions::NEAREST` for pixel art.
+    pub fn from_img_with_options<T>(name: T, img: ColorImage, options: TextureOptions, ctx: &Context) -> Self where T: Into<String> {
+        let bits = to_bitset(&img);
+        Self::from_img_and_bits(name, img, bits, options, ctx)
+    }
+
+    /// Same as `from_img_with_options` but keeps a copy of `img`, so that its colors can be read back
+    /// through `color_image` and `color_at`. The other constructors drop the image once it is uploaded.
+    pub fn from_img_retaining_colors<T>(name: T, img: ColorImage, options: TextureOptions, ctx: &Context) -> Self where T: Into<String> {
+        let color_image = img.clone();
+        let mut retained = Self::from_img_with_options(name, img, options, ctx);
+        retained.color_image = Some(color_image);
+        retained
+    }
+
+    /// Builds an image from tightly packed, unmultiplied RGBA8 bytes.
+    pub fn from_rgba<T>(name: T, width: usize, height: usize, rgba: &[u8], ctx: &Context) -> Result<Self, RgbaError> where T: Into<String> {
+        let expected = width.checked_mul(height).and_then(|c| c.checked_mul(4));
+        if expected != Some(rgba.len()) {
+            return Err(RgbaError::LengthMismatch { width, height, actual: rgba.len() });
+        }
+        let img = ColorImage::from_rgba_unmultiplied([width, height], rgba);
+        Ok(Self::from_img(name, img, ctx))
+    }
+
+    /// Decodes a PNG or JPEG file. Requires the `image` feature.
+    #[cfg(feature = "image")]
+    pub fn from_path<T, P>(name: T, path: P, ctx: &Context) -> Result<Self, ImageLoadError> where T: Into<String>, P: AsRef<Path> {
+        let decoded = image::open(path).map_err(|e| match e {
+            image::ImageError::IoError(io_err) if io_err.kind() == std::io::ErrorKind::NotFound => ImageLoadError::NotFound(io_err),
+            image::ImageError::IoError(io_err) => ImageLoadError::Io(io_err),
+            image::ImageError::Unsupported(e) => ImageLoadError::UnsupportedFormat(e.to_string()),
+            e => ImageLoadError::DecodeFailed(e.to_string()),
+        })?;
+        let rgba = decoded.to_rgba8();
+        let img = ColorImage::from_rgba_unmultiplied([rgba.width() as usize, rgba.height() as usize], rgba.as_raw());
+        Ok(Self::from_img(name, img, ctx))
+    }
+
+    /// Uses `color` as the texture and `mask` as the clickable region. A mask pixel is opaque when its
+    /// luminance exceeds `threshold`. Colors are premultiplied, so both a grayscale mask and a white mask
+    /// with an alpha channel work.
+    pub fn from_color_and_mask<T>(
+        name: T, color: ColorImage, mask: ColorImage, threshold: u8, ctx: &Context
+    ) -> Result<Self, MaskError> where T: Into<String> {
+        if color.size != mask.size {
+            return Err(MaskError::SizeMismatch { color: color.size, mask: mask.size });
+        }
+        let bits = to_bitset_luminance(&mask, threshold);
+        Ok(Self::from_img_and_bits(name, color, bits, TextureOptions::LINEAR, ctx))
+    }
+
+    fn from_img_and_bits<T>(name: T, img: ColorImage, bits: BitSet, options: TextureOptions, ctx: &Context) -> Self where T: Into<String> {
+        let name = name.into();
+        let texture = ctx.load_texture(name.clone(), img, options);
+        let size = texture.size();
+        let pixels = Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(size[0] as f32, size[1] as f32)));
+        let bit_img = BitImg::new(pixels);
+        Self {
+            name, bit_img, texture, color_image: None,
+        }
+    }
+
+    pub fn from_svg<T>(name: T, svg_bytes: &[u8], scale: f32, ctx: &Context) -> Result<Self, SvgError> where T: Into<String> {
+        Self::from_svg_with_options(name, svg_bytes, scale, TextureOptions::LINEAR, ctx)
+    }
+
+    /// Same as `from_svg` for SVG markup held as text.
+    pub fn from_svg_str<T>(name: T, svg: &str, scale: f32, ctx: &Context) -> Result<Self, SvgError> where T: Into<String> {
+        Self::from_svg(name, svg.as_bytes(), scale, ctx)
+    }
+
+    pub fn from_svg_with_options<T>(
+        name: T, svg_bytes: &[u8], scale: f32, options: TextureOptions, ctx: &Context
+    ) -> Result<Self, SvgError> where T: Into<String> {
+        let img = load_svg_bytes(svg_bytes, scale)?;
+        Ok(Self::from_img_with_options(name, img, options, ctx))
+    }
+
+    /// Loads an SVG whose alpha channel encodes a signed distance field, i.e. the shape is authored
+    /// with an alpha ramp across its outline and the visible edge lies at alpha 0.5. Only pixels with
+    /// alpha of 128 or more become clickable, instead of any non transparent pixel.
+    pub fn from_svg_sdf<T>(name: T, svg_bytes: &[u8], scale: f32, ctx: &Context) -> Result<Self, SvgError> where T: Into<String> {
+        let img = load_svg_bytes(svg_bytes, scale)?;
+        let bits = to_bitset_alpha(&img, SDF_ALPHA_THRESHOLD);
+        Ok(Self::from_img_and_bits(name, img, bits, TextureOptions::LINEAR, ctx))
+    }
+
+    /// Loads an SVG embedded with `include_bytes!` through `loader`, so that the rendered image is
+    /// served from the loader's disk cache (a sub directory of the platform cache directory) on later launches.
+    pub fn from_embedded_svg<T>(name: T, bytes: &'static [u8], loader: &SvgLoader, ctx: &Context) -> Result<Self, SvgError> where T: Into<String> {
+        let img = loader.load(bytes)?;
+        Ok(Self::from_img(name, img, ctx))
+    }
+
+    /// Tries each SVG in `candidates` in order and uses the first one that renders.
+    /// If every candidate fails, the error of the last one is returned.
+    pub fn from_svg_with_fallback<T>(name: T, candidates: &[&[u8]], scale: f32, ctx: &Context) -> Result<Self, SvgError> where T: Into<String> {
+        let mut last_err = SvgError::Other("No SVG candidates given.".to_owned());
+        for svg_bytes in candidates {
+            match load_svg_bytes(svg_bytes, scale) {
+                Ok(img) => return Ok(Self::from_img(name, img, ctx)),
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn size(&self) -> Vec2 {
+        self.texture.size_vec2()
+    }
+
+    #[inline]
+    pub fn is_opaque_at(&self, x: usize, y: usize) -> bool {
+        self.bit_img.is_opaque_at(x, y)
+    }
+
+    /// Opacity at an image-local position. The position is floored to a pixel; positions outside of
+    /// the image, including negative ones, are handled by the `OutOfBounds` policy (transparent by default).
+    #[inline]
+    pub fn is_opaque_at_pos(&self, pos: Pos2) -> bool {
+        self.bit_img.opaque_at_point(pos)
+    }
+
+    #[inline]
+    pub fn contains_pixel(&self, rect: &Rect) -> bool {
+        self.bit_img.contains_pixel(rect)
+    }
+
+    #[inline]
+    pub fn opaque_bounds(&self) -> Option<Rect> {
+        self.bit_img.opaque_bounds()
+    }
+
+    #[inline]
+    pub fn total_opaque(&self) -> usize {
+        self.bit_img.total_opaque()
+    }
+
+    #[inline]
+    pub fn is_fully_opaque(&self) -> bool {
+        self.bit_img.is_fully_opaque()
+    }
+
+    #[inline]
+    pub fn is_fully_transparent(&self) -> bool {
+        self.bit_img.is_fully_transparent()
+    }
+
+    /// The image given to `from_img_retaining_colors`, `None` for images built otherwise.
+    #[inline]
+    pub fn color_image(&self) -> Option<&ColorImage> {
+        self.color_image.as_ref()
+    }
+
+    /// Color of the pixel at (x, y), `None` if it is outside of the image or the colors are not retained.
+    pub fn color_at(&self, x: usize, y: usize) -> Option<Color32> {
+        let img = self.color_image.as_ref()?;
+        if img.width() <= x || img.height() <= y { return None; }
+        Some(img[(x, y)])
+    }
+
+    /// Whether both images have the same size and opacity mask, regardless of their names and textures.
+    pub fn same_mask(&self, other: &Img) -> bool {
+        self.bit_img.pixels == other.bit_img.pixels
+    }
+
+    #[inline]
+    pub fn out_of_bounds(&self) -> OutOfBounds {
+        self.bit_img.out_of_bounds()
+    }
+
+    pub fn set_out_of_bounds(&mut self, policy: OutOfBounds) {
+        self.bit_img.set_out_of_bounds(policy);
+    }
+
+    #[inline]
+    pub fn texture_id(&self) -> TextureId {
+        self.texture.id()
+    }
+
+    /// UV rect of the image within its texture. egui gives every `load_texture` call its own texture
+    /// and `TextureHandle` exposes no atlas placement, so this is always the full `[0, 1]` rect.
+    #[inline]
+    pub fn uv_bounds(&self) -> Rect {
+        Rect::from_min_max(Pos2::ZERO, Pos2::new(1., 1.))
+    }
+
+    /// Hit test against a screen position when the image is drawn (and possibly scaled) into `draw_rect`.
+    pub fn contains_screen_pos(&self, screen_pos: Pos2, draw_rect: Rect) -> bool {
+        screen_to_image(screen_pos, draw_rect, self.size()).map_or(false, |(x, y)| self.is_opaque_at(x, y))
+    }
+
+    /// Whether the pointer of `ui` is over an opaque pixel of the image drawn into `draw_rect`.
+    /// Use `contains_screen_pos` for other input sources.
+    pub fn hovered_opaque(&self, ui: &Ui, draw_rect: Rect) -> bool {
+        ui.input(|i| i.pointer.hover_pos()).map_or(false, |pos| self.contains_screen_pos(pos, draw_rect))
+    }
+
+    /// Allocates `draw_rect` and senses input on it only where the pointer is over an opaque pixel.
+    /// Over transparent pixels the rect is registered with `Sense::hover()` and no interact rect, so that
+    /// the image is not hovered and clicks reach the widgets beneath it on the same layer. Widgets on lower
+    /// layers stay unreachable, since egui stops at the container (e.g. the `Area`) holding the image,
+    /// which covers the pointer whatever the image senses. egui hit tests against the widgets
+    /// of the previous pass, so hovering can lag one pass behind when the pointer moves onto a transparent pixel.
+    pub fn interact(&self, ui: &mut Ui, id: Id, draw_rect: Rect, sense: Sense) -> Response {
+        ui.advance_cursor_after_rect(draw_rect);
+        self.sense_opaque(ui, id, draw_rect, draw_rect, sense)
+    }
+
+    // Senses `rect`, gated on the opacity of the image drawn into `draw_rect`.
+    fn sense_opaque(&self, ui: &mut Ui, id: Id, rect: Rect, draw_rect: Rect, sense: Sense) -> Response {
+        if self.hovered_opaque(ui, draw_rect) {
+            ui.interact(rect, id, sense)
+        } else {
+            // An empty clip rect keeps the widget out of egui's hit test, so it is neither hovered nor
+            // clicked while the response still covers `rect`.
+            let clip_rect = ui.clip_rect();
+            ui.set_clip_rect(Rect::NOTHING);
+            let response = ui.interact(rect, id, Sense::hover());
+            ui.set_clip_rect(clip_rect);
+            response
+        }
+    }
+
+    /// Paints the texture scaled into `draw_rect` and senses input on it as `interact` does, with the
+    /// automatic id of the `Ui`, so that the same image can be shown more than once. Call `interact` and
+    /// paint the image yourself when a stable id is needed.
+    pub fn show(&self, ui: &mut Ui, draw_rect: Rect, sense: Sense) -> Response {
+        let id = ui.advance_cursor_after_rect(draw_rect);
+        let response = self.sense_opaque(ui, id, draw_rect, draw_rect, sense);
+        if ui.is_rect_visible(draw_rect) {
+            ui.painter().image(self.texture_id(), draw_rect, self.uv_bounds(), Color32::WHITE);
+        }
+        response
+    }
+
+    /// Hit test against an image drawn with 9-slice scaling into `draw_rect`.
+    /// Corners keep their native size, edges stretch along one axis and the center along both.
+    /// If `draw_rect` is smaller than the sum of the insets along an axis, the corners along that
+    /// axis shrink proportionally and the center disappears.
+    pub fn contains_pixel_ninepatch(&self, screen_pos: Pos2, draw_rect: Rect, insets: Insets) -> bool {
+        if ! draw_rect.contains(screen_pos) { return false; }
+        let size = self.size();
+        let x = ninepatch_to_native(screen_pos.x - draw_rect.min.x, draw_rect.width(), size.x, insets.left, insets.right);
+        let y = ninepatch_to_native(screen_pos.y - draw_rect.min.y, draw_rect.height(), size.y, insets.top, insets.bottom);
+        if x < 0. || y < 0. || size.x <= x || size.y <= y { return false; }
+
+        self.is_opaque_at(x as usize, y as usize)
+    }
+}
+
+/// `Img` as a widget drawn at `size` (the native size by default), sensing `sense` (clicks by default)
+/// only over opaque pixels. `ui.add(&img)` is the same as `ui.add(ImgWidget::new(&img))`.
+pub struct ImgWidget<'a> {
+    img: &'a Img,
+    size: Vec2,
+    sense: Sense,
+}
+
+impl<'a> ImgWidget<'a> {
+    pub fn new(img: &'a Img) -> Self {
+        Self { img, size: img.size(), sense: Sense::click() }
+    }
+
+    pub fn size(mut self, size: Vec2) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn sense(mut self, sense: Sense) -> Self {
+        self.sense = sense;
+        self
+    }
+}
+
+impl Widget for ImgWidget<'_> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let (id, rect) = ui.allocate_space(self.size);
+        let response = self.img.sense_opaque(ui, id, rect, rect, self.sense);
+        if ui.is_rect_visible(rect) {
+            ui.painter().image(self.img.texture_id(), rect, self.img.uv_bounds(), Color32::WHITE);
+        }
+        response
+    }
+}
+
+impl Widget for &Img {
+    fn ui(self, ui: &mut Ui) -> Response {
+        ImgWidget::new(self).ui(ui)
+    }
+}
+
+/// Button showing an `Img` inside the usual button frame. Unlike `egui::ImageButton`, it is hovered,
+/// highlighted and clicked only while the pointer is over an opaque pixel of the image.
+pub struct ImgButton<'a> {
+    img: &'a Img,
+    size: Vec2,
+    frame: bool,
+}
+
+impl<'a> ImgButton<'a> {
+    pub fn new(img: &'a Img) -> Self {
+        Self { img, size: img.size(), frame: true }
+    }
+
+    /// Drawn size of the image, excluding the button padding.
+    pub fn size(mut self, size: Vec2) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Whether to draw the button frame. Defaults to true.
+    pub fn frame(mut self, frame: bool) -> Self {
+        self.frame = frame;
+        self
+    }
+}
+
+impl Widget for ImgButton<'_> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let padding = if self.frame { ui.spacing().button_padding } else { Vec2::ZERO };
+        let (id, rect) = ui.allocate_space(self.size + 2. * padding);
+        let image_rect = Rect::from_min_size(rect.min + padding, self.size);
+        let response = self.img.sense_opaque(ui, id, rect, image_rect, Sense::click());
+        if ui.is_rect_visible(rect) {
+            let visuals = ui.style().interact(&response);
+            if self.frame {
+                ui.painter().rect(rect.expand(visuals.expansion), visuals.rounding, visuals.weak_bg_fill, visuals.bg_stroke);
+            }
+            ui.painter().image(self.img.texture_id(), image_rect, self.img.uv_bounds(), Color32::WHITE);
+        }
+        response
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct Insets {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl Insets {
+    pub fn same(inset: f32) -> Self {
+        Self { left: inset, top: inset, right: inset, bottom: inset }
+    }
+}
+
+/// Pixel of an image of `image_size` pixels drawn (and possibly scaled) into `image_rect` under the
+/// screen position `pos`, or `None` if `pos` is outside of `image_rect`. The right and bottom edges
+/// of `image_rect` are outside. Hit test a pointer with
+/// `screen_to_image(pos, rect, img.size()).map_or(false, |(x, y)| img.is_opaque_at(x, y))`.
+pub fn screen_to_image(pos: Pos2, image_rect: Rect, image_size: Vec2) -> Option<(usize, usize)> {
+    let to_pixel = |pos: f32, min: f32, max: f32, len: f32| {
+        if ! (min..max).contains(&pos) || len < 1. { return None; }
+        // Rounding may land exactly on `len` next to the far edge.
+        Some((((pos - min) * len / (max - min)) as usize).min(len as usize - 1))
+    };
+    let x = to_pixel(pos.x, image_rect.min.x, image_rect.max.x, image_size.x)?;
+    let y = to_pixel(pos.y, image_rect.min.y, image_rect.max.y, image_size.y)?;
+    Some((x, y))
+}
+
+// Maps a position along one axis of a 9-slice drawn image back to the native image.
+fn ninepatch_to_native(pos: f32, drawn_len: f32, native_len: f32, start: f32, end: f32) -> f32 {
+    let corners = start + end;
+    if drawn_len < corners {
+        let ratio = drawn_len / corners;
+        let drawn_start = start * ratio;
+        if pos < drawn_start {
+            pos / ratio
+        } else {
+            native_len - (drawn_len - pos) / ratio
+        }
+    } else if pos < start {
+        pos
+    } else if drawn_len - end <= pos {
+        native_len - (drawn_len - pos)
+    } else {
+        start + (pos - start) * (native_len - corners) / (drawn_len - corners)
+    }
+}
+
+/// Collects images to be turned into `Img`s at a single commit point.
+#[derive(Default)]
+pub struct ImgBatch {
+    entries: Vec<(String, ColorImage, Option<BitSet>)>,
+}
+
+impl ImgBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<T>(&mut self, name: T, img: ColorImage) -> &mut Self where T: Into<String> {
+        self.entries.push((name.into(), img, None));
+        self
+    }
+
+    /// Registers an image whose mask is given instead of being derived from its alpha.
+    pub fn add_with_mask<T>(&mut self, name: T, img: ColorImage, mask: BitSet) -> &mut Self where T: Into<String> {
+        self.entries.push((name.into(), img, Some(mask)));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Builds all masks first and then uploads all textures. The returned `Img`s are in the order
+    /// they were added.
+    pub fn commit(self, ctx: &Context) -> Vec<Img> {
+        let prepared: Vec<(String, ColorImage, BitImg)> = self.entries.into_iter().map(|(name, img, mask)| {
+            let bits = mask.unwrap_or_else(|| to_bitset(&img));
+            let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(img.width() as f32, img.height() as f32));
+            let bit_img = BitImg::new(Pixels2D::new(bits, rect));
+            (name, img, bit_img)
+        }).collect();
+
+        prepared.into_iter().map(|(name, img, bit_img)| Img {
+            texture: ctx.load_texture(name.clone(), img, TextureOptions::LINEAR),
+            name,
+            bit_img,
+            color_image: None,
+        }).collect()
+    }
+}
+
+#[cfg(feature = "image")]
+#[derive(Debug)]
+pub enum ImageLoadError {
+    NotFound(std::io::Error),
+    Io(std::io::Error),
+    UnsupportedFormat(String),
+    DecodeFailed(String),
+}
+
+#[cfg(feature = "image")]
+impl std::fmt::Display for ImageLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageLoadError::NotFound(err) => write!(f, "Image file not found: {}", err),
+            ImageLoadError::Io(err) => write!(f, "Cannot read image file: {}", err),
+            ImageLoadError::UnsupportedFormat(msg) => write!(f, "Unsupported image format: {}", msg),
+            ImageLoadError::DecodeFailed(msg) => write!(f, "Cannot decode image: {}", msg),
+        }
+    }
+}
+
+#[cfg(feature = "image")]
+impl std::error::Error for ImageLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageLoadError::NotFound(err) | ImageLoadError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RgbaError {
+    LengthMismatch { width: usize, height: usize, actual: usize },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MaskError {
+    SizeMismatch { color: [usize; 2], mask: [usize; 2] },
+}
+
+#[derive(Debug)]
+pub enum SvgError {
+    CannotParse(usvg::Error),
+    CannotLoad { width: u32, height: u32 },
+    /// The output would exceed the maximum dimension, given in pixels.
+    TooLarge { width: u32, height: u32 },
+    CannotRender,
+    Other(String),
+}
+
+impl std::fmt::Display for SvgError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SvgError::CannotParse(err) => write!(f, "Cannot parse SVG: {}", err),
+            SvgError::CannotLoad { width, height } => write!(f, "Cannot allocate a {}x{} pixmap for SVG", width, height),
+            SvgError::TooLarge { width, height } => write!(f, "SVG output of {}x{} exceeds the maximum dimension", width, height),
+            SvgError::CannotRender => write!(f, "Cannot render SVG"),
+            SvgError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SvgError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SvgError::CannotParse(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<usvg::Error> for SvgError {
+    fn from(err: usvg::Error) -> Self {
+        SvgError::CannotParse(err)
+    }
+}
+
+pub const DEFAULT_MEMORY_CACHE_ENTRIES: usize = 64;
+
+// Rendered images kept in memory, least recently used first.
+struct MemoryCache {
+    entries: Vec<(String, ColorImage)>,
+    max_entries: Option<usize>,
+    max_bytes: Option<usize>,
+}
+
+impl MemoryCache {
+    fn get(&mut self, key: &str) -> Option<ColorImage> {
+        let idx = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(idx);
+        let img = entry.1.clone();
+        self.entries.push(entry);
+        Some(img)
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.entries.retain(|(k, _)| k != key);
+    }
+
+    fn insert(&mut self, key: String, img: ColorImage) {
+        self.entries.retain(|(k, _)| *k != key);
+        self.entries.push((key, img));
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        let image_bytes = |img: &ColorImage| img.pixels.len() * std::mem::size_of::<Color32>();
+        let mut total_bytes: usize = self.entries.iter().map(|(_, img)| image_bytes(img)).sum();
+        while !self.entries.is_empty() && (
+            self.max_entries.map_or(false, |max| max < self.entries.len()) ||
+            self.max_bytes.map_or(false, |max| max < total_bytes)
+        ) {
+            let (_, img) = self.entries.remove(0);
+            total_bytes -= image_bytes(&img);
+        }
+    }
+}
+
+/// Loads SVGs through two cache layers: an in-memory LRU of rendered images and, when a cache
+/// directory is given, the disk cache. The memory layer holds `DEFAULT_MEMORY_CACHE_ENTRIES` images
+/// by default and is managed independently of the disk cache.
+///
+/// `SvgLoader` is `Send + Sync`, so it can be shared through an `Arc` and `load` called from several
+/// threads. The memory layer is guarded by a mutex. Disk entries are written by `local_file_cache`, which
+/// writes a temporary file and renames it into place, so readers never see a partially written entry;
+/// this relies on that crate's write protocol. Two threads loading the same SVG before it is cached may
+/// both render it; both get the same image and the entry is written once.
+pub struct SvgLoader {
+    pub scale: f32,
+    cache_dir: Option<PathBuf>,
+    namespace: Option<PathBuf>,
+    prefix: Option<String>,
+    ttl: Option<Duration>,
+    max_cache_bytes: Option<u64>,
+    // Current time as seen by the disk cache. Replaced in tests instead of sleeping.
+    now: fn() -> std::time::SystemTime,
+    memory: Mutex<MemoryCache>,
+    // When this loader last read or wrote each disk entry. Access times cannot be relied on for eviction
+    // since relatime and noatime mounts do not update them on reads.
+    disk_used: Mutex<HashMap<PathBuf, std::time::SystemTime>>,
+    renders: AtomicUsize,
+    disk_hits: AtomicUsize,
+    disk_misses: AtomicUsize,
+}
+
+/// Disk cache lookups of an `SvgLoader`. Loads served from the memory layer or made without a disk
+/// cache are not counted.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct CacheStats {
+    /// Images read from the disk cache.
+    pub hits: usize,
+    /// Images rendered and written to the disk cache.
+    pub misses: usize,
+}
+
+impl SvgLoader {
+    /// `cache_dir` is either relative to the platform cache directory or absolute.
+    pub fn new<P>(scale: f32, cache_dir: Option<P>) -> Self where P: AsRef<Path> {
+        Self {
+            scale,
+            cache_dir: cache_dir.map(|p| p.as_ref().to_path_buf()),
+            namespace: None,
+            prefix: None,
+            ttl: None,
+            max_cache_bytes: None,
+            now: std::time::SystemTime::now,
+            memory: Mutex::new(MemoryCache {
+                entries: Vec::new(),
+                max_entries: Some(DEFAULT_MEMORY_CACHE_ENTRIES),
+                max_bytes: None,
+            }),
+            disk_used: Mutex::new(HashMap::new()),
+            renders: AtomicUsize::new(0),
+            disk_hits: AtomicUsize::new(0),
+            disk_misses: AtomicUsize::new(0),
+        }
+    }
+
+    /// Keeps the disk cache entries in the sub directory `namespace` of the cache directory, so that
+    /// different sets of images do not share one directory. Without it entries are stored directly in
+    /// the cache directory.
+    pub fn with_namespace<P>(mut self, namespace: P) -> Self where P: AsRef<Path> {
+        self.namespace = Some(namespace.as_ref().to_path_buf());
+        self
+    }
+
+    /// Names the disk cache entries `<prefix>_<hash>` instead of just `<hash>`.
+    pub fn with_prefix<S>(mut self, prefix: S) -> Self where S: Into<String> {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Treats disk cache entries modified more than `ttl` ago as missing, rendering and overwriting them
+    /// on the next load. Entries whose modification time is not available are considered fresh.
+    /// Without it entries never expire.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Limits the total size of the files in the disk cache directory (including the namespace). After
+    /// an entry is written, the least recently used files are removed until the total fits. A file counts
+    /// as used when this loader reads or writes it; files it has not touched are ranked by their
+    /// modification time, so uses by other processes are not seen. Eviction is best effort: errors
+    /// are ignored, and a file removed while another thread or process reads it only turns that read
+    /// into a miss. The entry just written is never evicted.
+    pub fn with_max_cache_bytes(mut self, max_cache_bytes: u64) -> Self {
+        self.max_cache_bytes = Some(max_cache_bytes);
+        self
+    }
+
+    pub fn max_cache_bytes(&self) -> Option<u64> {
+        self.max_cache_bytes
+    }
+
+    pub fn namespace(&self) -> Option<&Path> {
+        self.namespace.as_deref()
+    }
+
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    pub fn load(&self, svg_bytes: &[u8]) -> Result<egui::ColorImage, SvgError> {
+        let hex_str = cache_key(self.scale, svg_bytes);
+        if self.is_stale(&hex_str) {
+            // A stale entry that cannot be removed is overwritten by the render below anyway.
+            let _ = self.invalidate(svg_bytes);
+        }
+        if let Some(img) = self.memory().get(&hex_str) {
+            return Ok(img);
+        }
+
+        let render = || {
+            self.renders.fetch_add(1, Ordering::Relaxed);
+            load_svg_bytes(svg_bytes, self.scale)
+        };
+        let img = match self.disk_cache() {
+            Some(cache) => {
+                let mut rendered = false;
+                let mut result = cache.or_insert_with(self.entry_name(&hex_str), || {
+                    rendered = true;
+                    render()
+                });
+                // Failed renders are never written, so an error read from disk is an entry that does not
+                // decode, e.g. one written in an older format. It is rendered again.
+                if !rendered && matches!(result, Ok(Err(_))) {
+                    if let Some(path) = self.entry_path(&hex_str) {
+                        let _ = std::fs::remove_file(path);
+                    }
+                    result = cache.or_insert_with(self.entry_name(&hex_str), || {
+                        rendered = true;
+                        render()
+                    });
+                }
+                let counter = if rendered { &self.disk_misses } else { &self.disk_hits };
+                counter.fetch_add(1, Ordering::Relaxed);
+                if let Some(path) = self.entry_path(&hex_str) {
+                    self.disk_used().insert(path, (self.now)());
+                }
+                if rendered {
+                    self.evict_disk_entries(&hex_str);
+                }
+                match result {
+                    Ok(ok) => ok,
+                    Err(io_err) => Err(SvgError::Other(io_err.to_string()))
+                }
+            },
+            None => render(),
+        }?;
+
+        self.memory().insert(hex_str, img.clone());
+        Ok(img)
+    }
+
+    /// Drops the image of `svg_bytes` at the scale of the loader from both the memory and the disk
+    /// cache, so that the next `load` renders it again. A missing entry is not an error.
+    pub fn invalidate(&self, svg_bytes: &[u8]) -> std::io::Result<()> {
+        let hex_str = cache_key(self.scale, svg_bytes);
+        self.memory().remove(&hex_str);
+        match self.entry_path(&hex_str) {
+            Some(path) => {
+                self.disk_used().remove(&path);
+                match std::fs::remove_file(&path) {
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                    result => result,
+                }
+            },
+            None => Ok(()),
+        }
+    }
+
+    pub fn memory_cache_len(&self) -> usize {
+        self.memory().entries.len()
+    }
+
+    /// Drops every image held in memory. The disk cache is left untouched.
+    pub fn memory_cache_clear(&self) {
+        self.memory().entries.clear();
+    }
+
+    /// Limits the number of images held in memory, evicting the least recently used ones. `None` means unlimited.
+    pub fn set_memory_cache_max_entries(&self, max_entries: Option<usize>) {
+        let mut memory = self.memory();
+        memory.max_entries = max_entries;
+        memory.evict();
+    }
+
+    /// Limits the total pixel bytes held in memory, evicting the least recently used images. `None` means unlimited.
+    pub fn set_memory_cache_max_bytes(&self, max_bytes: Option<usize>) {
+        let mut memory = self.memory();
+        memory.max_bytes = max_bytes;
+        memory.evict();
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.disk_hits.load(Ordering::Relaxed),
+            misses: self.disk_misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Directory of the disk cache as given to `new`. A relative directory is resolved against the
+    /// platform cache directory.
+    pub fn cache_dir(&self) -> Option<&Path> {
+        self.cache_dir.as_deref()
+    }
+
+    /// A view of the disk cache, standing in for the removed public `cache` field. Every call returns a
+    /// new, detached `LocalFileCache` over the same directory, so the loader never sees changes made to
+    /// the returned value; only the files it writes are shared. A stored `LocalFileCache` would keep the
+    /// loader from being `Send + Sync`.
+    #[deprecated(note = "returns a new LocalFileCache detached from the loader on every call; use `cache_dir`, `invalidate` and `cache_stats` instead")]
+    pub fn cache(&self) -> Option<LocalFileCache<Result<ColorImage, SvgError>>> {
+        self.disk_cache()
+    }
+
+    fn entry_name(&self, hex_str: &str) -> String {
+        match &self.prefix {
+            Some(prefix) => format!("{}_{}", prefix, hex_str),
+            None => hex_str.to_owned(),
+        }
+    }
+
+    // Absolute directory of the entries. This is the only place the cache directory is resolved; the
+    // result is handed to LocalFileCache as is, which keeps an absolute path instead of prepending its base.
+    fn entry_dir(&self) -> Option<PathBuf> {
+        let dir = self.cache_dir.as_ref()?;
+        let dir = match &self.namespace {
+            Some(namespace) => dir.join(namespace),
+            None => dir.clone(),
+        };
+        if dir.is_absolute() { Some(dir) } else { dirs::cache_dir().map(|base| base.join(dir)) }
+    }
+
+    fn is_stale(&self, hex_str: &str) -> bool {
+        let (ttl, path) = match (self.ttl, self.entry_path(hex_str)) {
+            (Some(ttl), Some(path)) => (ttl, path),
+            _ => return false,
+        };
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+            .and_then(|modified| (self.now)().duration_since(modified).ok())
+            .map_or(false, |age| ttl < age)
+    }
+
+    fn evict_disk_entries(&self, keep: &str) {
+        let (max_bytes, keep) = match (self.max_cache_bytes, self.entry_path(keep)) {
+            (Some(max_bytes), Some(keep)) => (max_bytes, keep),
+            _ => return,
+        };
+        let dir = match keep.parent().and_then(|dir| std::fs::read_dir(dir).ok()) {
+            Some(dir) => dir,
+            None => return,
+        };
+        let mut files: Vec<(std::time::SystemTime, u64, PathBuf)> = dir.filter_map(|entry| {
+            let entry = entry.ok()?;
+            let meta = entry.metadata().ok()?;
+            if ! meta.is_file() { return None; }
+            let path = entry.path();
+            let used = match self.disk_used().get(&path) {
+                Some(used) => *used,
+                None => meta.modified().unwrap_or(std::time::UNIX_EPOCH),
+            };
+            Some((used, meta.len(), path))
+        }).collect();
+        let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+        files.sort_by_key(|(used, _, _)| *used);
+        for (_, len, path) in files {
+            if total <= max_bytes { break; }
+            // Temporary files being written are skipped as well.
+            if path == keep || path.extension().map_or(false, |ext| ext == "save") { continue; }
+            if std::fs::remove_file(&path).is_ok() {
+                total -= len;
+                self.disk_used().remove(&path);
+            }
+        }
+    }
+
+    fn entry_path(&self, hex_str: &str) -> Option<PathBuf> {
+        self.entry_dir().map(|dir| dir.join(self.entry_name(hex_str)))
+    }
+
+    // LocalFileCache holds closures that are neither Send nor Sync, so it is built for each load
+    // instead of being kept in the loader. This is cheap as it only resolves the directory.
+    fn disk_cache(&self) -> Option<LocalFileCache<Result<ColorImage, SvgError>>> {
+        self.entry_dir().and_then(|dir| LocalFileCache::<Result<ColorImage, SvgError>>::new(dir,
+            Box::new(|img|
+                match img {
+                    // Images too large for the entry header are not written.
+                    Ok(ci) => img_to_u8(ci).ok(),
+                    Err(_) => None,
+                }
+            ),
+            Box::new(|bin| u8_to_img(bin).map_err(|e| SvgError::Other(format!("Broken cache entry: {:?}", e))))
+        ))
+    }
+
+    fn memory(&self) -> std::sync::MutexGuard<'_, MemoryCache> {
+        self.memory.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn disk_used(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, std::time::SystemTime>> {
+        self.disk_used.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn cache_key(scale: f32, svg_bytes: &[u8]) -> String {
+    let mut hash = Sha256::default();
+    <u8 as Hash>::hash_slice(&scale.to_ne_bytes(), &mut hash);
+    <u8 as Hash>::hash_slice(svg_bytes, &mut hash);
+    format!("{:x}", hash.finish())
+}
+
+/// Keeps up to `capacity` built `Img`s (with their textures) in memory, keyed by the SVG bytes and the
+/// scale of the loader, so that rebuilding the UI does not upload the same texture again. Misses go
+/// through the `SvgLoader`; the least recently used `Img` is dropped, freeing its texture, when full.
+pub struct ImgCache {
+    loader: SvgLoader,
+    entries: Vec<(String, Img)>,
+    capacity: usize,
+}
+
+impl ImgCache {
+    /// A `capacity` of 0 is treated as 1 since the last loaded `Img` is always kept.
+    pub fn new(loader: SvgLoader, capacity: usize) -> Self {
+        Self {
+            loader,
+            entries: Vec::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Returns the cached `Img` for the SVG, or loads and caches it under `name`. The name of a cached
+    /// `Img` is the one given when it was loaded.
+    pub fn get_or_load<T>(&mut self, name: T, svg_bytes: &[u8], ctx: &Context) -> Result<&Img, SvgError> where T: Into<String> {
+        let key = cache_key(self.loader.scale, svg_bytes);
+        match self.entries.iter().position(|(k, _)| *k == key) {
+            Some(idx) => {
+                let entry = self.entries.remove(idx);
+                self.entries.push(entry);
+            },
+            None => {
+                let img = Img::from_img(name, self.loader.load(svg_bytes)?, ctx);
+                self.entries.push((key, img));
+                self.evict();
+            },
+        }
+        Ok(&self.entries.last().unwrap().1)
+    }
+
+    pub fn loader(&self) -> &SvgLoader {
+        &self.loader
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.evict();
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn evict(&mut self) {
+        if self.capacity < self.entries.len() {
+            self.entries.drain(0..(self.entries.len() - self.capacity));
+        }
+    }
+}
+
+pub fn load_svg_bytes(svg_bytes: &[u8], scale: f32) -> Result<egui::ColorImage, SvgError> {
+    load_svg_bytes_with(svg_bytes, scale, &ResvgRasterizer::default())
+}
+
+/// Same as `load_svg_bytes` for SVG markup held as text.
+pub fn load_svg_str(svg: &str, scale: f32) -> Result<egui::ColorImage, SvgError> {
+    load_svg_bytes(svg.as_bytes(), scale)
+}
+
+pub fn load_svg_bytes_with(svg_bytes: &[u8], scale: f32, rasterizer: &dyn SvgRasterizer) -> Result<egui::ColorImage, SvgError> {
+    let opt = usvg::Options::default();
+    let usvg_tree: usvg::Tree = usvg::Tree::from_data(svg_bytes, &opt)?;
+    rasterizer.rasterize(&usvg_tree, scale)
+}
+
+/// Renders the SVG with `stylesheet` injected through `usvg::Options::style_sheet` (usvg 0.42 or later),
+/// e.g. `path { stroke: blue }` to recolor an icon. The stylesheet takes precedence over presentation
+/// attributes. Only the selectors and properties usvg understands apply; CSS custom properties
+/// (`--icon-color`) are not supported.
+pub fn load_svg_bytes_styled(svg_bytes: &[u8], scale: f32, stylesheet: &str) -> Result<egui::ColorImage, SvgError> {
+    let opt = usvg::Options {
+        style_sheet: Some(stylesheet.to_owned()),
+        ..usvg::Options::default()
+    };
+    load_svg_bytes_with_options(svg_bytes, scale, &opt)
+}
+
+/// Renders the SVG parsed with the given options. `usvg::Options::default()` has no fonts, so SVGs
+/// containing `<text>` need a `fontdb` with fonts registered (e.g. `load_system_fonts()`) to render their labels.
+pub fn load_svg_bytes_with_options(svg_bytes: &[u8], scale: f32, opt: &usvg::Options) -> Result<egui::ColorImage, SvgError> {
+    let usvg_tree: usvg::Tree = usvg::Tree::from_data(svg_bytes, opt)?;
+    ResvgRasterizer::default().rasterize(&usvg_tree, scale)
+}
+
+/// Renders the SVG over `background` instead of a transparent pixmap. A background that is not
+/// transparent makes every pixel opaque, so `contains_pixel` is true everywhere, which suits solid tiles.
+pub fn load_svg_bytes_with_background(svg_bytes: &[u8], scale: f32, background: Option<Color32>) -> Result<egui::ColorImage, SvgError> {
+    let opt = usvg::Options::default();
+    let usvg_tree: usvg::Tree = usvg::Tree::from_data(svg_bytes, &opt)?;
+    let size = usvg_tree.size();
+    let w = size.width().ceil() as usize;
+    let h = size.height().ceil() as usize;
+    check_dimension((w as f32) * scale, (h as f32) * scale, DEFAULT_MAX_SVG_DIMENSION)?;
+
+    render_tree(&usvg_tree, ((w as f32) * scale) as u32, ((h as f32) * scale) as u32, usvg::Transform::from_scale(scale, scale), background)
+        .ok_or(SvgError::CannotLoad { width: w as u32, height: h as u32})
+}
+
+/// Turns a parsed SVG into pixels. `load_svg_bytes` uses `ResvgRasterizer`; tests that must not depend
+/// on the exact output of resvg can pass another implementation to `load_svg_bytes_with`.
+pub trait SvgRasterizer {
+    fn rasterize(&self, tree: &usvg::Tree, scale: f32) -> Result<egui::ColorImage, SvgError>;
+}
+
+pub const DEFAULT_MAX_SVG_DIMENSION: u32 = 16384;
+
+/// Renders with resvg. Outputs wider or taller than `max_dimension` pixels fail with `SvgError::TooLarge`
+/// before any allocation, so that a bad scale does not exhaust memory.
+pub struct ResvgRasterizer {
+    pub max_dimension: u32,
+}
+
+impl Default for ResvgRasterizer {
+    fn default() -> Self {
+        Self { max_dimension: DEFAULT_MAX_SVG_DIMENSION }
+    }
+}
+
+impl SvgRasterizer for ResvgRasterizer {
+    fn rasterize(&self, usvg_tree: &usvg::Tree, scale: f32) -> Result<egui::ColorImage, SvgError> {
+        let size = usvg_tree.size();
+        let w = size.width().ceil() as usize;
+        let h = size.height().ceil() as usize;
+        check_dimension((w as f32) * scale, (h as f32) * scale, self.max_dimension)?;
+
+        render_tree(usvg_tree, ((w as f32) * scale) as u32, ((h as f32) * scale) as u32, usvg::Transform::from_scale(scale, scale), None)
+            .ok_or(SvgError::CannotLoad { width: w as u32, height: h as u32})
+    }
+}
+
+fn check_dimension(width: f32, height: f32, max_dimension: u32) -> Result<(), SvgError> {
+    // Float to int casts saturate, so huge or infinite sizes become u32::MAX.
+    let (width, height) = (width as u32, height as u32);
+    if max_dimension < width.max(height) { return Err(SvgError::TooLarge { width, height }); }
+    Ok(())
+}
+
+fn render_tree(usvg_tree: &usvg::Tree, width: u32, height: u32, transform: usvg::Transform, background: Option<Color32>) -> Option<egui::ColorImage> {
+    let mut pixmap = resvg::tiny_skia::Pixmap::new(width, height)?;
+    if let Some(color) = background {
+        let [r, g, b, a] = color.to_srgba_unmultiplied();
+        pixmap.fill(resvg::tiny_skia::Color::from_rgba8(r, g, b, a));
+    }
+    resvg::render(usvg_tree, transform, &mut pixmap.as_mut());
+
+    Some(egui::ColorImage::from_rgba_unmultiplied(
+        [pixmap.width() as usize, pixmap.height() as usize], pixmap.data(),
+    ))
+}
+
+/// Width and height of the SVG at scale 1.0, parsed without rendering.
+pub fn svg_intrinsic_size(svg_bytes: &[u8]) -> Result<Vec2, SvgError> {
+    let opt = usvg::Options::default();
+    let usvg_tree: usvg::Tree = usvg::Tree::from_data(svg_bytes, &opt)?;
+    let size = usvg_tree.size();
+    Ok(Vec2::new(size.width(), size.height()))
+}
+
+/// How `load_svg_bytes_sized` maps the SVG onto a target size of a different aspect ratio.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum AspectMode {
+    /// Scales each axis independently to the target size.
+    #[default]
+    Stretch,
+    /// Scales to fit inside of the target and centers, leaving transparent padding.
+    Fit,
+    /// Scales to cover the target and centers, clipping the overflow.
+    Fill,
+}
+
+/// Renders the SVG into exactly `width` x `height` pixels, mapping the content according to `mode`.
+pub fn load_svg_bytes_sized(svg_bytes: &[u8], width: u32, height: u32, mode: AspectMode) -> Result<egui::ColorImage, SvgError> {
+    check_dimension(width as f32, height as f32, DEFAULT_MAX_SVG_DIMENSION)?;
+    let opt = usvg::Options::default();
+    let usvg_tree: usvg::Tree = usvg::Tree::from_data(svg_bytes, &opt)?;
+    let size = usvg_tree.size();
+    let sx = width as f32 / size.width();
+    let sy = height as f32 / size.height();
+    let centered = |s: f32| usvg::Transform::from_row(
+        s, 0., 0., s, (width as f32 - size.width() * s) / 2., (height as f32 - size.height() * s) / 2.
+    );
+    let transform = match mode {
+        AspectMode::Stretch => usvg::Transform::from_scale(sx, sy),
+        AspectMode::Fit => centered(sx.min(sy)),
+        AspectMode::Fill => centered(sx.max(sy)),
+    };
+
+    render_tree(&usvg_tree, width, height, transform, None).ok_or(SvgError::CannotLoad { width, height })
+}
+
+/// Deterministic stand-in for resvg: fills the bounding box of every visible path with opaque black.
+/// A pixel is filled when its center lies inside a bounding box.
+#[cfg(any(test, feature = "test-rasterizer"))]
+pub struct BoundingBoxRasterizer;
+
+#[cfg(any(test, feature = "test-rasterizer"))]
+impl SvgRasterizer for BoundingBoxRasterizer {
+    fn rasterize(&self, usvg_tree: &usvg::Tree, scale: f32) -> Result<egui::ColorImage, SvgError> {
+        fn fill_group(group: &usvg::Group, scale: f32, img: &mut ColorImage) {
+            for node in group.children() {
+                match node {
+                    usvg::Node::Group(g) => fill_group(g, scale, img),
+                    usvg::Node::Path(path) if path.is_visible() => {
+                        let bbox = path.abs_bounding_box();
+                        for y in 0..img.height() {
+                            for x in 0..img.width() {
+                                let cx = (x as f32 + 0.5) / scale;
+                                let cy = (y as f32 + 0.5) / scale;
+                                if bbox.left() <= cx && cx < bbox.right() && bbox.top() <= cy && cy < bbox.bottom() {
+                                    img[(x, y)] = Color32::BLACK;
+                                }
+                            }
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+
+        let size = usvg_tree.size();
+        let w = ((size.width().ceil()) * scale) as usize;
+        let h = ((size.height().ceil()) * scale) as usize;
+        let mut img = ColorImage::new([w, h], Color32::TRANSPARENT);
+        fill_group(usvg_tree.root(), scale, &mut img);
+        Ok(img)
+    }
+}
+
+/// Renders the SVG and removes fully transparent outer rows and columns. Returns the trimmed image
+/// and the region it occupied in the untrimmed rendering, whose `min` is the crop offset.
+pub fn load_svg_bytes_trimmed(svg_bytes: &[u8], scale: f32) -> Result<(egui::ColorImage, Rect), SvgError> {
+    let img = load_svg_bytes(svg_bytes, scale)?;
+    Ok(trim_transparent(&img))
+}
+
+/// Alpha threshold of the default mask. A pixel is opaque when its alpha is above it, whatever its
+/// color channels are, so that a premultiplied color with zero RGB but nonzero alpha is opaque.
+pub const OPAQUE_ALPHA_THRESHOLD: u8 = 0;
+
+#[inline]
+fn is_opaque(c: Color32) -> bool {
+    OPAQUE_ALPHA_THRESHOLD < c.a()
+}
+
+/// Crops fully transparent outer rows and columns. A fully transparent image is trimmed to 0x0.
+pub fn trim_transparent(img: &ColorImage) -> (ColorImage, Rect) {
+    let w = img.width();
+    let h = img.height();
+    let is_opaque = |x: usize, y: usize| is_opaque(img[(x, y)]);
+    let row_is_empty = |y: usize| (0..w).all(|x| !is_opaque(x, y));
+    let column_is_empty = |x: usize| (0..h).all(|y| !is_opaque(x, y));
+
+    let top = match (0..h).find(|y| !row_is_empty(*y)) {
+        Some(top) => top,
+        None => return (ColorImage::new([0, 0], Color32::TRANSPARENT), Rect::from_min_size(Pos2::ZERO, Vec2::ZERO)),
+    };
+    let bottom = (0..h).rev().find(|y| !row_is_empty(*y)).unwrap_or(top) + 1;
+    let left = (0..w).find(|x| !column_is_empty(*x)).unwrap_or(0);
+    let right = (0..w).rev().find(|x| !column_is_empty(*x)).unwrap_or(left) + 1;
+
+    let mut pixels = Vec::with_capacity((right - left) * (bottom - top));
+    for y in top..bottom {
+        pixels.extend_from_slice(&img.pixels[(y * w + left)..(y * w + right)]);
+    }
+
+    (
+        ColorImage { size: [right - left, bottom - top], pixels },
+        Rect::from_min_size(Pos2::new(left as f32, top as f32), Vec2::new((right - left) as f32, (bottom - top) as f32)),
+    )
+}
+
+/// `to_bitset` packed into a `Bitmap` for `Pixels2D::from_bitmap`.
+pub fn to_bitmap(img: &ColorImage) -> Bitmap {
+    let mut bitmap = Bitmap::new(img.pixels.len());
+    for (idx, pixel) in img.pixels.iter().enumerate() {
+        if is_opaque(*pixel) { bitmap.insert(idx); }
+    }
+    bitmap
+}
+
+pub fn to_bitset(img: &ColorImage) -> BitSet {
+    to_bitset_by(img, is_opaque)
+}
+
+/// Alpha threshold of `Img::from_svg_sdf`. Pixels with alpha above it (the 0.5 edge) are opaque.
+pub const SDF_ALPHA_THRESHOLD: u8 = 127;
+
+pub fn to_bitset_alpha(img: &ColorImage, threshold: u8) -> BitSet {
+    to_bitset_by(img, |c| threshold < c.a())
+}
+
+pub fn to_bitset_luminance(img: &ColorImage, threshold: u8) -> BitSet {
+    to_bitset_by(img, |c| {
+        let luminance = (c.r() as u32 * 299 + c.g() as u32 * 587 + c.b() as u32 * 114) / 1000;
+        (threshold as u32) < luminance
+    })
+}
+
+/// Pixels whose red, green and blue are each within `tolerance` of `key` are transparent, the others
+/// opaque. For images keyed with a solid background color instead of an alpha channel. Alpha is ignored.
+pub fn to_bitset_chroma(img: &ColorImage, key: Color32, tolerance: u8) -> BitSet {
+    to_bitset_by(img, |c| {
+        let keyed = c.r().abs_diff(key.r()) <= tolerance
+            && c.g().abs_diff(key.g()) <= tolerance
+            && c.b().abs_diff(key.b()) <= tolerance;
+        ! keyed
+    })
+}
+
+fn to_bitset_by(img: &ColorImage, opaque: impl Fn(Color32) -> bool) -> BitSet {
+    let w = img.width();
+    let h = img.height();
+    let mut bitset = BitSet::with_capacity(w * h);
+    for y in 0..h {
+        for x in 0..w {
+            if opaque(img[(x, y)]) {
+                bitset.insert(w * y + x);
+            }
+        }
+    }
+
+    bitset
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use std::sync::atomic::Ordering;
+    use bit_set::BitSet;
+    use egui::{ColorImage, Color32, Context, Rect, Pos2, Vec2, TextureOptions};
+    use local_file_cache::LocalFileCache;
+    use crate::{load_svg_str, AspectMode, Component, ImgWidget, ImgButton, screen_to_image, CacheStats, SplitStrategy, TreeStats, Img, ImgBatch, ImgCache, BitImg, Connectivity, CoverageMode, Insets, MaskError, OutOfBounds, RgbaError, Pixels2D, TreeOptions, LayeredRect, load_svg_bytes, load_svg_bytes_sized, load_svg_bytes_trimmed, load_svg_bytes_with, load_svg_bytes_styled, load_svg_bytes_with_options, load_svg_bytes_with_background, BoundingBoxRasterizer, ResvgRasterizer, svg_intrinsic_size, SvgLoader, SvgError};
+
+    use super::{to_bitmap, to_bitset, to_bitset_chroma, img_to_u8, u8_to_img, cache_key};
+
+    const T: Color32 = Color32::TRANSPARENT;
+    const B: Color32 = Color32::BLACK;
+
+    // O___O
+    // __OO_
+    // OOO__
+    // _OO__
+    // _____
+    fn sample_5x5_pixels() -> Pixels2D {
+        let mut bitset = BitSet::with_capacity(25);
+        for i in [0, 4, 7, 8, 10, 11, 12, 16, 17] {
+            bitset.insert(i);
+        }
+        Pixels2D::new(bitset, Rect::from_min_size(Pos2::ZERO, Vec2::new(5., 5.)))
+    }
+
+    fn sample_5x5() -> BitImg {
+        BitImg::new(sample_5x5_pixels())
+    }
+
+    // Removes a disk cache directory, relative to the platform cache directory or absolute, which may not
+    // exist yet.
+    fn clear_cache<P: AsRef<std::path::Path>>(dir: P) {
+        if let Err(e) = LocalFileCache::<()>::invalidate(dir).unwrap() {
+            if e.kind() != ErrorKind::NotFound {
+                panic!("Unexpected error {:?}", e);
+            }
+        }
+    }
+
+    #[test]
+    fn img_can_convert_to_bitset() {
+        let img = ColorImage {
+            size: [8, 16],
+            pixels: vec![
+                //  0  1  2  3  4  5  6  7    
+                T, T, T, T, T, T, T, T, // 0
+                T, T, T, T, T, T, T, T, // 1
+                T, T, B, T, T, T, B, B, // 2
+                T, T, T, T, T, T, B, T, // 3
+                T, T, T, T, T, T, T, T, // 4
+                T, T, T, T, T, T, T, T, // 5
+                T, B, B, T, T, T, T, T, // 6
+                T, T, T, T, T, T, T, T, // 7
+                T, T, T, T, T, T, T, T, // 8
+                T, T, T, T, T, T, T, T, // 9
+                T, T, T, T, T, T, T, T, // 10
+                T, T, T, T, T, T, T, T, // 11
+                T, T, T, T, T, T, T, B, // 12
+                T, T, T, T, T, T, T, T, // 13
+                B, B, T, T, T, T, T, T, // 14
+                T, T, T, T, T, T, T, T, // 15
+            ],
+        };
+        let bitset = to_bitset(&img);
+        assert!(!bitset.contains(0));
+        assert!(bitset.contains(18));
+        assert!(bitset.contains(22));
+        assert!(bitset.contains(23));
+        assert!(bitset.contains(30));
+        assert!(bitset.contains(49));
+        assert!(bitset.contains(50));
+    }
+
+    #[test]
+    fn is_opaque() {
+        let img = ColorImage {
+            size: [8, 16],
+            pixels: vec![
+                //  0  1  2  3  4  5  6  7    
+                T, T, T, T, T, T, T, T, // 0
+                T, T, T, T, T, T, T, T, // 1
+                T, T, B, T, T, T, B, B, // 2
+                T, T, T, T, T, T, B, T, // 3
+                T, T, T, T, T, T, T, T, // 4
+                T, T, T, T, T, T, T, T, // 5
+                T, B, B, T, T, T, T, T, // 6
+                T, T, T, T, T, T, T, T, // 7
+                T, T, T, T, T, T, T, T, // 8
+                T, T, T, T, T, T, T, T, // 9
+                T, T, T, T, T, T, T, T, // 10
+                T, T, T, T, T, T, T, T, // 11
+                T, T, T, T, T, T, T, B, // 12
+                T, T, T, T, T, T, T, T, // 13
+                B, B, T, T, T, T, T, T, // 14
+                T, T, T, T, T, T, T, T, // 15
+            ],
+        };
+
+        let ctx = Context::default();
+        let img = Img::from_img("test", img, &ctx);
+
+        assert!(!img.is_opaque_at(0, 0));
+        assert!(img.is_opaque_at(2, 2));
+        assert!(img.is_opaque_at(6, 2));
+        assert!(img.is_opaque_at(6, 2));
+        assert!(img.is_opaque_at(0, 14));
+        assert!(img.is_opaque_at(1, 14));
+        assert!(!img.is_opaque_at(7, 15));
+    }
+
+    #[test]
+    fn small_bitimg_becomes_leaf() {
+        // O__
+        // __O
+        // OOO
+        let mut bit_set = BitSet::with_capacity(9);
+        bit_set.insert(0);
+        bit_set.insert(5);
+        bit_set.insert(6);
+        bit_set.insert(7);
+        bit_set.insert(8);
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(3., 3.));
+        let bit_img = Pixels2D::new(bit_set, rect);
+        let layered = LayeredRect::new(rect, &bit_img);
+        assert_eq!(layered, LayeredRect::Leaf { rect, pixel_count: 5 });
+    }
+
+    #[test]
+    fn bitimg_split_horizontal() {
+        // O__O
+        // __O_
+        // OOO_
+        let mut bit_set = BitSet::with_capacity(12);
+        bit_set.insert(0);
+        bit_set.insert(3);
+        bit_set.insert(6);
+        bit_set.insert(8);
+        bit_set.insert(9);
+        bit_set.insert(10);
+
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(4., 3.));
+        let bit_img = Pixels2D::new(bit_set, rect);
+        let layered = LayeredRect::new(rect, &bit_img);
+        let [left, right]: [LayeredRect; 2] = match layered {
+            LayeredRect::Leaf { rect: _, pixel_count: _ } => { panic!("Test failed."); },
+            LayeredRect::Node { rect: r, children, pixel_count } => {
+                assert_eq!(rect, r);
+                assert_eq!(pixel_count, 6);
+                children.try_into().unwrap()
+            },
+        };
+
+        // Left
+        // O_
+        // __
+        // OO
+        match left {
+            LayeredRect::Node { rect: _, children: _, pixel_count: _ } => { panic!("Test failed."); },
+            LayeredRect::Leaf { rect, pixel_count } => {
+                assert_eq!(pixel_count, 3);
+                assert_eq!(rect, Rect::from_min_size(Pos2::ZERO, Vec2::new(2., 3.)));
+            },
+        };
+
+        // Right
+        // _O
+        // O_
+        // O_
+        match right {
+            LayeredRect::Node { rect: _, children: _, pixel_count: _ } => { panic!("Test failed."); },
+            LayeredRect::Leaf { rect, pixel_count } => {
+                assert_eq!(pixel_count, 3);
+                assert_eq!(rect, Rect::from_min_size(Pos2::new(2., 0.), Vec2::new(2., 3.)));
+            },
+        }
+    }
+
+    #[test]
+    fn pixels2d_can_determine_rect_contains_pixels() {
+        // O__
+        // __O
+        // OOO
+        let mut bitset = BitSet::with_capacity(9);
+        bitset.insert(0);
+        bitset.insert(5);
+        bitset.insert(6);
+        bitset.insert(7);
+        bitset.insert(8);
+
+        let pixels = Pixels2D::new(bitset, Rect::from_min_size(Pos2::ZERO, Vec2::new(3., 3.)));
+        assert!(pixels.contains_pixel(&Rect::from_min_size(Pos2::ZERO, Vec2::new(1.0, 1.0))));
+        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(1.0, 1.0))));
+        assert!(pixels.contains_pixel(&Rect::from_min_size(Pos2::ZERO, Vec2::new(1.0, 2.0))));
+        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(1.0, 2.0))));
+        assert!(pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(1.0, 3.0))));
+        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(2.0, 1.0))));
+        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(2.0, 0.0), Vec2::new(2.0, 1.0))));
+        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(10.0, 10.0), Vec2::new(20.0, 20.0))));
+    }
+
+    #[test]
+    fn bitimg_can_determine_rect_contains_pixels() {
+        // O___O
+        // __OO_
+        // OOO__
+        // _OO__
+        // _____
+        let mut bitset = BitSet::with_capacity(25);
+        bitset.insert(0);
+        bitset.insert(4);
+        bitset.insert(7);
+        bitset.insert(8);
+        bitset.insert(10);
+        bitset.insert(11);
+        bitset.insert(12);
+        bitset.insert(16);
+        bitset.insert(17);
+
+        let pixels = Pixels2D::new(bitset, Rect::from_min_size(Pos2::ZERO, Vec2::new(5., 5.)));
+        assert!(pixels.contains_pixel(&Rect::from_min_size(Pos2::ZERO, Vec2::new(1.0, 1.0))));
+        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(1.0, 1.0))));
+        assert!(pixels.contains_pixel(&Rect::from_min_size(Pos2::ZERO, Vec2::new(1.0, 2.0))));
+        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(1.0, 2.0))));
+        assert!(pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(1.0, 3.0))));
+        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(2.0, 1.0))));
+        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(2.0, 0.0), Vec2::new(2.0, 1.0))));
+        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(3.0, 2.0), Vec2::new(2.0, 2.0))));
+        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(0.0, 4.0), Vec2::new(2.0, 2.0))));
+        assert!(pixels.contains_pixel(&Rect::from_min_size(Pos2::new(0.0, 3.0), Vec2::new(3.0, 3.0))));
+        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(10.0, 10.0), Vec2::new(20.0, 20.0))));
+    }
+    
+    const TEST_SVG: &[u8] = br#"<?xml version="1.0" standalone="no"?>
+    <!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd" >
+    <svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" version="1.1" viewBox="0 0 100 100">
+       <rect x="0" y="0" width="99" height="99" style="fill:rgb(255,0,0);stroke-width:1"/>
+    </svg>
+    "#;
+    
+    #[test]
+    fn img_contains_pixel() {
+        let img: ColorImage = load_svg_bytes(TEST_SVG, 1.0).unwrap();
+        let ctx = Context::default();
+        let img: Img = Img::from_img("test", img, &ctx);
+        assert!(!img.contains_pixel(&Rect::from_min_size(Pos2::new(99., 0.), Vec2::new(1., 1.))));
+        assert!(img.contains_pixel(&Rect::from_min_size(Pos2::new(0., 0.), Vec2::new(1., 1.))));
+    }
+
+    #[test]
+    fn svg_to_img() {
+        let img: ColorImage = load_svg_bytes(TEST_SVG, 1.0).unwrap();
+        assert_eq!(img.width(), 100);
+        assert_eq!(img.height(), 100);
+        assert_eq!(img[(0, 0)], Color32::RED);
+        assert_eq!(img[(98, 0)], Color32::RED);
+        assert_eq!(img[(99, 0)], Color32::TRANSPARENT);
+
+        assert_eq!(img[(0, 98)], Color32::RED);
+        assert_eq!(img[(0, 99)], Color32::TRANSPARENT);
+
+        assert_eq!(img[(98, 98)], Color32::RED);
+        assert_eq!(img[(99, 99)], Color32::TRANSPARENT);
+    }
+
+    #[test]
+    fn can_cache() {
+        let img: ColorImage = load_svg_bytes(TEST_SVG, 0.1).unwrap();
+        if let Err(e) = LocalFileCache::<()>::invalidate("my_test").unwrap() {
+            if e.kind() != ErrorKind::NotFound {
+                panic!("Unexpected error {:?}", e);
+            }
+        }
+        let loader = SvgLoader::new(0.1, Some("my_test"));
+        let cached = loader.load(TEST_SVG).unwrap();
+
+        assert_eq!(img.size, cached.size);
+        assert_eq!(img.pixels, cached.pixels);
+
+        let cached = loader.load(TEST_SVG).unwrap();
+        assert_eq!(img.size, cached.size);
+        assert_eq!(img.pixels, cached.pixels);
+
+        for x in 0..cached.width() {
+            for y in 0..cached.height() {
+                assert_eq!(cached[(x, y)], Color32::RED);
+            }
+        }
+    }
+
+    #[test]
+    fn do_split_horizontal() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(10.0, 5.0));
+        let [left, right] = crate::split_horizontal(&rect);
+        assert_eq!(left, Rect::from_min_size(Pos2::ZERO, Vec2::new(5.0, 5.0)));
+        assert_eq!(right, Rect::from_min_size(Pos2::new(5.0, 0.0), Vec2::new(5.0, 5.0)));
+
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(5.0, 3.0));
+        let [left, right] = crate::split_horizontal(&rect);
+        assert_eq!(left, Rect::from_min_size(Pos2::ZERO, Vec2::new(2.0, 3.0)));
+        assert_eq!(right, Rect::from_min_size(Pos2::new(2.0, 0.0), Vec2::new(3.0, 3.0)));
+
+        let rect = Rect::from_min_size(Pos2::new(100.0, 200.0), Vec2::new(10.0, 5.0));
+        let [left, right] = crate::split_horizontal(&rect);
+        assert_eq!(left, Rect::from_min_size(Pos2::new(100.0, 200.0), Vec2::new(5.0, 5.0)));
+        assert_eq!(right, Rect::from_min_size(Pos2::new(105.0, 200.0), Vec2::new(5.0, 5.0)));
+    }
+
+    #[test]
+    fn do_split_vertical() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(10.0, 20.0));
+        let [left, right] = crate::split_vertical(&rect);
+        assert_eq!(left, Rect::from_min_size(Pos2::ZERO, Vec2::new(10.0, 10.0)));
+        assert_eq!(right, Rect::from_min_size(Pos2::new(0.0, 10.0), Vec2::new(10.0, 10.0)));
+
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(10.0, 11.0));
+        let [left, right] = crate::split_vertical(&rect);
+        assert_eq!(left, Rect::from_min_size(Pos2::ZERO, Vec2::new(10.0, 5.0)));
+        assert_eq!(right, Rect::from_min_size(Pos2::new(0.0, 5.0), Vec2::new(10.0, 6.0)));
+
+        let rect = Rect::from_min_size(Pos2::new(100.0, 200.0), Vec2::new(10.0, 5.0));
+        let [left, right] = crate::split_vertical(&rect);
+        assert_eq!(left, Rect::from_min_size(Pos2::new(100.0, 200.0), Vec2::new(10.0, 2.0)));
+        assert_eq!(right, Rect::from_min_size(Pos2::new(100.0, 202.0), Vec2::new(10.0, 3.0)));
+    }
+
+    #[test]
+    fn can_compute_iou() {
+        // OO__
+        // ____
+        let mut bits0 = BitSet::with_capacity(8);
+        bits0.insert(0);
+        bits0.insert(1);
+        // _OO_
+        // ____
+        let mut bits1 = BitSet::with_capacity(8);
+        bits1.insert(1);
+        bits1.insert(2);
+        // ____
+        // __OO
+        let mut bits2 = BitSet::with_capacity(8);
+        bits2.insert(6);
+        bits2.insert(7);
+
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(4., 2.));
+        let img0 = BitImg::new(Pixels2D::new(bits0, rect));
+        let img1 = BitImg::new(Pixels2D::new(bits1, rect));
+        let img2 = BitImg::new(Pixels2D::new(bits2, rect));
+        let empty = BitImg::new(Pixels2D::new(BitSet::new(), rect));
+
+        assert_eq!(img0.iou(&img0), 1.0);
+        assert_eq!(img0.iou(&img1), 1.0 / 3.0);
+        assert_eq!(img0.iou(&img2), 0.0);
+        assert_eq!(img0.iou(&empty), 0.0);
+        assert_eq!(empty.iou(&empty), 1.0);
+    }
+
+    #[test]
+    fn ninepatch_hit_test() {
+        // 0 1 2 3 4 5
+        // O _ _ _ _ O 0
+        // _ _ O _ _ _ 1
+        // _ _ _ _ _ _ 2
+        // O _ _ _ _ O 3
+        let mut pixels = vec![T; 24];
+        pixels[0] = B;
+        pixels[5] = B;
+        pixels[8] = B;
+        pixels[18] = B;
+        pixels[23] = B;
+        let ctx = Context::default();
+        let img = Img::from_img("test", ColorImage { size: [6, 4], pixels }, &ctx);
+        let insets = Insets::same(1.);
+
+        // Drawn at 60x40 from (100, 100). Corners stay 1px wide.
+        let draw_rect = Rect::from_min_size(Pos2::new(100., 100.), Vec2::new(60., 40.));
+        assert!(img.contains_pixel_ninepatch(Pos2::new(100.5, 100.5), draw_rect, insets));
+        assert!(!img.contains_pixel_ninepatch(Pos2::new(101.5, 100.5), draw_rect, insets));
+        assert!(img.contains_pixel_ninepatch(Pos2::new(159.5, 139.5), draw_rect, insets));
+        assert!(!img.contains_pixel_ninepatch(Pos2::new(158.5, 139.5), draw_rect, insets));
+        // Center (native 1..5 x 1..3) is stretched over 58x38, native (2, 1) maps to x in [115.5, 130), y in [101, 120).
+        assert!(img.contains_pixel_ninepatch(Pos2::new(120., 110.), draw_rect, insets));
+        assert!(!img.contains_pixel_ninepatch(Pos2::new(140., 110.), draw_rect, insets));
+        assert!(!img.contains_pixel_ninepatch(Pos2::new(120., 130.), draw_rect, insets));
+        // Outside of the draw rect.
+        assert!(!img.contains_pixel_ninepatch(Pos2::new(99., 100.5), draw_rect, insets));
+
+        // Smaller than the corners: corners shrink to half.
+        let draw_rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(1., 1.));
+        assert!(img.contains_pixel_ninepatch(Pos2::new(0.25, 0.25), draw_rect, insets));
+        assert!(img.contains_pixel_ninepatch(Pos2::new(0.75, 0.75), draw_rect, insets));
+    }
+
+    #[test]
+    fn coverage_is_smoothed_at_edges() {
+        // OOO__
+        // OOO__
+        // OOO__
+        // _____
+        let mut bits = BitSet::with_capacity(20);
+        for y in 0..3 {
+            for x in 0..3 {
+                bits.insert(x + y * 5);
+            }
+        }
+        let img = BitImg::new(Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(5., 4.))));
+
+        assert_eq!(img.coverage_at(0, 0), 1.0);
+        assert_eq!(img.coverage_at(1, 1), 1.0);
+        assert_eq!(img.coverage_at(2, 1), 6.0 / 9.0);
+        assert_eq!(img.coverage_at(3, 1), 3.0 / 9.0);
+        assert_eq!(img.coverage_at(2, 2), 4.0 / 9.0);
+        assert_eq!(img.coverage_at(4, 3), 0.0);
+        assert_eq!(img.coverage_at(5, 0), 0.0);
+    }
+
+    #[test]
+    fn img_from_color_and_mask() {
+        const W: Color32 = Color32::WHITE;
+        const G: Color32 = Color32::from_gray(100);
+        let color = ColorImage { size: [3, 1], pixels: vec![Color32::RED, Color32::GREEN, Color32::BLUE] };
+        let mask = ColorImage {
+            size: [3, 1],
+            pixels: vec![W, B, G],
+        };
+        let ctx = Context::default();
+        let img = Img::from_color_and_mask("test", color.clone(), mask.clone(), 128, &ctx).unwrap();
+        assert!(img.is_opaque_at(0, 0));
+        assert!(!img.is_opaque_at(1, 0));
+        assert!(!img.is_opaque_at(2, 0));
+        let img = Img::from_color_and_mask("test", color.clone(), mask, 50, &ctx).unwrap();
+        assert!(img.is_opaque_at(2, 0));
+
+        let mask = ColorImage { size: [1, 3], pixels: vec![W, W, W] };
+        assert_eq!(
+            Img::from_color_and_mask("test", color, mask, 128, &ctx).err(),
+            Some(MaskError::SizeMismatch { color: [3, 1], mask: [1, 3] })
+        );
+    }
+
+    #[test]
+    fn can_enumerate_leaves_intersecting() {
+        // 6x6 image is split into 3x6 halves, then into 3x3 leaves.
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(6., 6.));
+        let img = BitImg::new(Pixels2D::new(BitSet::new(), rect));
+
+        let leaves = img.leaves_intersecting(&Rect::from_min_size(Pos2::new(0.5, 0.5), Vec2::new(1., 1.))).unwrap();
+        assert_eq!(leaves, vec![Rect::from_min_size(Pos2::ZERO, Vec2::new(3., 3.))]);
+
+        let leaves = img.leaves_intersecting(&Rect::from_min_size(Pos2::new(3.5, 3.5), Vec2::new(1., 1.))).unwrap();
+        assert_eq!(leaves, vec![Rect::from_min_size(Pos2::new(3., 3.), Vec2::new(3., 3.))]);
+
+        assert_eq!(img.leaves_intersecting(&rect).unwrap().len(), 4);
+        assert!(img.leaves_intersecting(&Rect::from_min_size(Pos2::new(10., 10.), Vec2::new(1., 1.))).unwrap().is_empty());
+    }
+
+    #[test]
+    fn interact_only_on_opaque_pixels() {
+        // O_
+        // __
+        let img = ColorImage { size: [2, 2], pixels: vec![B, T, T, T] };
+        let ctx = Context::default();
+        let img = Img::from_img("test", img, &ctx);
+        let draw_rect = Rect::from_min_size(Pos2::new(10., 10.), Vec2::new(20., 20.));
+
+        assert!(img.contains_screen_pos(Pos2::new(15., 15.), draw_rect));
+        assert!(!img.contains_screen_pos(Pos2::new(25., 15.), draw_rect));
+        assert!(!img.contains_screen_pos(Pos2::new(5., 5.), draw_rect));
+
+        let hovered_at = |pos: Pos2| {
+            let mut hovered = false;
+            for _ in 0..2 {
+                let input = egui::RawInput {
+                    events: vec![egui::Event::PointerMoved(pos)],
+                    ..Default::default()
+                };
+                let _ = ctx.run(input, |ctx| {
+                    egui::CentralPanel::default().show(ctx, |ui| {
+                        hovered = img.interact(ui, egui::Id::new("img"), draw_rect, egui::Sense::click()).hovered();
+                    });
+                });
+            }
+            hovered
+        };
+        assert!(hovered_at(Pos2::new(15., 15.)));
+        assert!(!hovered_at(Pos2::new(25., 25.)));
+    }
+
+    #[test]
+    fn clicks_pass_through_transparent_pixels() {
+        // O_
+        // __
+        let img = ColorImage { size: [2, 2], pixels: vec![B, T, T, T] };
+        let ctx = Context::default();
+        let img = Img::from_img("test", img, &ctx);
+        let draw_rect = Rect::from_min_size(Pos2::new(10., 10.), Vec2::new(20., 20.));
+
+        // Whether the widget beneath and the image are clicked.
+        let click_at = |pos: Pos2, lower_layer: bool| {
+            let (mut below_clicked, mut img_clicked) = (false, false);
+            let frames = [
+                vec![egui::Event::PointerMoved(pos)],
+                vec![egui::Event::PointerMoved(pos)],
+                vec![egui::Event::PointerButton { pos, button: egui::PointerButton::Primary, pressed: true, modifiers: Default::default() }],
+                vec![egui::Event::PointerButton { pos, button: egui::PointerButton::Primary, pressed: false, modifiers: Default::default() }],
+            ];
+            for events in frames {
+                let input = egui::RawInput { events, ..Default::default() };
+                let _ = ctx.run(input, |ctx| {
+                    egui::CentralPanel::default().show(ctx, |ui| {
+                        below_clicked = ui.interact(draw_rect, egui::Id::new("below"), egui::Sense::click()).clicked();
+                        if !lower_layer {
+                            img_clicked = img.interact(ui, egui::Id::new("img"), draw_rect, egui::Sense::click()).clicked();
+                        }
+                    });
+                    if lower_layer {
+                        egui::Area::new(egui::Id::new("over")).fixed_pos(draw_rect.min).show(ctx, |ui| {
+                            img_clicked = img.interact(ui, egui::Id::new("img"), draw_rect, egui::Sense::click()).clicked();
+                        });
+                    }
+                });
+            }
+            (below_clicked, img_clicked)
+        };
+        assert_eq!(click_at(Pos2::new(25., 25.), false), (true, false));
+        assert_eq!(click_at(Pos2::new(15., 15.), false), (false, true));
+        // On a layer above, the area holding the image covers the widget beneath, so the click stops there.
+        assert_eq!(click_at(Pos2::new(25., 25.), true), (false, false));
+        assert_eq!(click_at(Pos2::new(15., 15.), true), (false, true));
+    }
+
+    #[test]
+    fn can_compute_histograms() {
+        let img = ColorImage {
+            size: [8, 16],
+            pixels: vec![
+                //  0  1  2  3  4  5  6  7    
+                T, T, T, T, T, T, T, T, // 0
+                T, T, T, T, T, T, T, T, // 1
+                T, T, B, T, T, T, B, B, // 2
+                T, T, T, T, T, T, B, T, // 3
+                T, T, T, T, T, T, T, T, // 4
+                T, T, T, T, T, T, T, T, // 5
+                T, B, B, T, T, T, T, T, // 6
+                T, T, T, T, T, T, T, T, // 7
+                T, T, T, T, T, T, T, T, // 8
+                T, T, T, T, T, T, T, T, // 9
+                T, T, T, T, T, T, T, T, // 10
+                T, T, T, T, T, T, T, T, // 11
+                T, T, T, T, T, T, T, B, // 12
+                T, T, T, T, T, T, T, T, // 13
+                B, B, T, T, T, T, T, T, // 14
+                T, T, T, T, T, T, T, T, // 15
+            ],
+        };
+        let bits = to_bitset(&img);
+        let img = BitImg::new(Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(8., 16.))));
+
+        assert_eq!(img.row_histogram(), vec![0, 0, 3, 1, 0, 0, 2, 0, 0, 0, 0, 0, 1, 0, 2, 0]);
+        assert_eq!(img.column_histogram(), vec![1, 2, 2, 0, 0, 0, 2, 2]);
+    }
+
+    #[test]
+    fn svg_fallback() {
+        let ctx = Context::default();
+        let img = Img::from_svg_with_fallback("test", &[b"<svg", TEST_SVG], 1.0, &ctx).unwrap();
+        assert_eq!(img.size(), Vec2::new(100., 100.));
+
+        match Img::from_svg_with_fallback("test", &[TEST_SVG.split_at(10).0, b"<svg"], 1.0, &ctx) {
+            Err(SvgError::CannotParse(_)) => {},
+            _ => panic!("Test failed."),
+        }
+        match Img::from_svg_with_fallback("test", &[], 1.0, &ctx) {
+            Err(SvgError::Other(_)) => {},
+            _ => panic!("Test failed."),
+        }
+    }
+
+    #[test]
+    fn can_load_embedded_svg() {
+        clear_cache("clickable_img_embedded_test");
+        let loader = SvgLoader::new(0.1, Some("clickable_img_embedded_test"));
+        let ctx = Context::default();
+        let img = Img::from_embedded_svg("test", TEST_SVG, &loader, &ctx).unwrap();
+        assert_eq!(img.size(), Vec2::new(10., 10.));
+        let img = Img::from_embedded_svg("test", TEST_SVG, &loader, &ctx).unwrap();
+        assert!(img.is_opaque_at(0, 0));
+        clear_cache("clickable_img_embedded_test");
+    }
+
+    #[test]
+    fn can_trim_transparent_border() {
+        const PADDED_SVG: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 100">
+           <rect x="10" y="20" width="30" height="40" style="fill:rgb(255,0,0)"/>
+        </svg>
+        "#;
+        let (img, region) = load_svg_bytes_trimmed(PADDED_SVG, 1.0).unwrap();
+        assert_eq!(img.size, [30, 40]);
+        assert_eq!(region, Rect::from_min_size(Pos2::new(10., 20.), Vec2::new(30., 40.)));
+        assert_eq!(img[(0, 0)], Color32::RED);
+        assert_eq!(img[(29, 39)], Color32::RED);
+
+        let (img, region) = crate::trim_transparent(&ColorImage::new([3, 3], T));
+        assert_eq!(img.size, [0, 0]);
+        assert_eq!(region.size(), Vec2::ZERO);
+    }
+
+    #[test]
+    fn hovered_opaque_follows_pointer() {
+        // _O
+        let img = ColorImage { size: [2, 1], pixels: vec![T, B] };
+        let ctx = Context::default();
+        let img = Img::from_img("test", img, &ctx);
+        let draw_rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(2., 1.));
+
+        let hovered_at = |events: Vec<egui::Event>| {
+            let mut hovered = false;
+            let _ = ctx.run(egui::RawInput { events, ..Default::default() }, |ctx| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    hovered = img.hovered_opaque(ui, draw_rect);
+                });
+            });
+            hovered
+        };
+        assert!(hovered_at(vec![egui::Event::PointerMoved(Pos2::new(1.5, 0.5))]));
+        assert!(!hovered_at(vec![egui::Event::PointerMoved(Pos2::new(0.5, 0.5))]));
+        assert!(!hovered_at(vec![egui::Event::PointerGone]));
+    }
+
+    #[test]
+    fn tree_depth_can_be_limited() {
+        // O______O
+        // ________
+        // ...
+        // ___O____
+        let mut bits = BitSet::with_capacity(64);
+        bits.insert(0);
+        bits.insert(7);
+        bits.insert(59);
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(8., 8.));
+        let unlimited = BitImg::new(Pixels2D::new(bits.clone(), rect));
+        assert_eq!(unlimited.layered_rect.depth(), 4);
+
+        for max_depth in 0..4 {
+            let limited = BitImg::with_options(Pixels2D::new(bits.clone(), rect), TreeOptions { max_depth: Some(max_depth), ..TreeOptions::default() });
+            assert_eq!(limited.layered_rect.depth(), max_depth);
+            assert_eq!(limited.layered_rect.pixel_count(), 3);
+            assert!(limited.contains_pixel(&Rect::from_min_size(Pos2::new(3., 7.), Vec2::new(1., 1.))));
+            assert!(!limited.contains_pixel(&Rect::from_min_size(Pos2::new(4., 7.), Vec2::new(1., 1.))));
+        }
+    }
+
+    #[test]
+    fn can_count_opaque_pixels_with_cap() {
+        let full = Rect::from_min_size(Pos2::ZERO, Vec2::new(5., 5.));
+        let img = sample_5x5();
+
+        let rects = [
+            full,
+            Rect::from_min_size(Pos2::new(1., 1.), Vec2::new(3., 3.)),
+            Rect::from_min_size(Pos2::new(-2., 2.), Vec2::new(4., 10.)),
+            Rect::from_min_size(Pos2::new(3., 3.), Vec2::new(2., 2.)),
+        ];
+        for rect in rects.iter() {
+            let full_count = img.pixels.pixel_count(rect.intersect(full));
+            for cap in 0..12 {
+                assert_eq!(img.opaque_count_in_capped(rect, cap), full_count.min(cap));
+            }
+        }
+
+        // Disjoint from the image, and touching its right edge only.
+        assert_eq!(img.opaque_count_in_capped(&Rect::from_min_size(Pos2::new(10., 10.), Vec2::new(2., 2.)), 5), 0);
+        assert_eq!(img.opaque_count_in_capped(&Rect::from_min_size(Pos2::new(5., 0.), Vec2::new(2., 5.)), 5), 0);
+        let mut img = img;
+        img.set_out_of_bounds(OutOfBounds::Clamp);
+        // Clamped onto the rightmost column, which holds (4, 0).
+        assert_eq!(img.opaque_count_in_capped(&Rect::from_min_size(Pos2::new(5., 0.), Vec2::new(2., 5.)), 5), 1);
+    }
+
+    #[test]
+    fn sdf_svg_uses_half_alpha_edge() {
+        const SDF_SVG: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 20 10">
+           <rect x="0" y="0" width="10" height="10" style="fill:rgb(0,0,0);fill-opacity:0.4"/>
+           <rect x="10" y="0" width="10" height="10" style="fill:rgb(0,0,0);fill-opacity:0.6"/>
+        </svg>
+        "#;
+        let ctx = Context::default();
+        let img = Img::from_svg_sdf("test", SDF_SVG, 1.0, &ctx).unwrap();
+        assert!(!img.is_opaque_at(5, 5));
+        assert!(img.is_opaque_at(15, 5));
+
+        let img = Img::from_svg("test", SDF_SVG, 1.0, &ctx).unwrap();
+        assert!(img.is_opaque_at(5, 5));
+        assert!(img.is_opaque_at(15, 5));
+    }
+
+    #[test]
+    fn can_query_multiple_points() {
+        let img = sample_5x5();
+
+        let mut points = vec![Pos2::new(-0.5, 0.), Pos2::new(5., 0.), Pos2::new(0., 5.), Pos2::new(f32::NAN, 1.)];
+        for y in 0..5 {
+            for x in 0..5 {
+                points.push(Pos2::new(4.5 - x as f32, y as f32 + 0.25));
+            }
+        }
+        let expected: Vec<bool> = points.iter().map(|p| img.opaque_at_point(*p)).collect();
+        assert_eq!(img.opaque_at_points(&points), expected);
+        // The 25 pixels fit in a single word.
+        assert_eq!(img.opaque_at_points_counted(&points).1, 1);
+
+        // A 64x64 image has one word per row.
+        let wide = BitImg::new(Pixels2D::new(BitSet::new(), Rect::from_min_size(Pos2::ZERO, Vec2::new(64., 64.))));
+        let clustered: Vec<Pos2> = (0..32).map(|i| Pos2::new(i as f32, 10.)).collect();
+        assert_eq!(wide.opaque_at_points_counted(&clustered).1, 1);
+        let scattered: Vec<Pos2> = (0..32).map(|i| Pos2::new(i as f32, i as f32 * 2.)).collect();
+        assert_eq!(wide.opaque_at_points_counted(&scattered).1, 32);
+        assert_eq!(expected.iter().filter(|b| **b).count(), 9);
+        assert!(img.opaque_at_point(Pos2::new(0.9, 0.9)));
+        assert!(!img.opaque_at_point(Pos2::new(1.0, 0.9)));
+    }
+
+    #[test]
+    fn coverage_mode_for_straddling_rect() {
+        // ___
+        // _O_
+        // ___
+        let mut bits = BitSet::with_capacity(9);
+        bits.insert(4);
+        let img = BitImg::new(Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(3., 3.))));
+
+        // Covers the quarters of (0, 0), (1, 0), (0, 1) and (1, 1). Only the center of (0, 0) is inside.
+        let rect = Rect::from_min_size(Pos2::new(0.5, 0.5), Vec2::new(1., 1.));
+        assert!(img.contains_pixel_coverage(&rect, CoverageMode::AnyOverlap));
+        assert!(!img.contains_pixel_coverage(&rect, CoverageMode::CenterInside));
+        assert_eq!(img.contains_pixel(&rect), img.contains_pixel_coverage(&rect, CoverageMode::CenterInside));
+
+        // Covers the center of (1, 1).
+        let rect = Rect::from_min_size(Pos2::new(0.6, 0.6), Vec2::new(1., 1.));
+        assert!(img.contains_pixel_coverage(&rect, CoverageMode::AnyOverlap));
+        assert!(img.contains_pixel_coverage(&rect, CoverageMode::CenterInside));
+
+        // Touches (1, 1) at its corner only.
+        let rect = Rect::from_min_size(Pos2::new(0., 0.), Vec2::new(1., 1.));
+        assert!(!img.contains_pixel_coverage(&rect, CoverageMode::AnyOverlap));
+        assert!(!img.contains_pixel_coverage(&rect, CoverageMode::CenterInside));
+
+        // Pixel aligned rects give the same answer in any mode.
+        let rect = Rect::from_min_size(Pos2::new(1., 0.), Vec2::new(2., 2.));
+        assert!(img.contains_pixel(&rect));
+        assert!(img.contains_pixel_coverage(&rect, CoverageMode::AnyOverlap));
+        assert!(img.contains_pixel_coverage(&rect, CoverageMode::CenterInside));
+    }
+
+    #[test]
+    fn batch_commit_keeps_order() {
+        let ctx = Context::default();
+        let mut mask = BitSet::new();
+        mask.insert(1);
+        let mut batch = ImgBatch::new();
+        batch
+            .add("first", ColorImage { size: [2, 1], pixels: vec![B, T] })
+            .add_with_mask("second", ColorImage { size: [2, 1], pixels: vec![B, B] }, mask)
+            .add("third", ColorImage { size: [1, 3], pixels: vec![T, T, B] });
+        assert_eq!(batch.len(), 3);
+
+        let imgs = batch.commit(&ctx);
+        assert_eq!(imgs.len(), 3);
+        assert!(imgs[0].is_opaque_at(0, 0));
+        assert!(!imgs[0].is_opaque_at(1, 0));
+        assert!(!imgs[1].is_opaque_at(0, 0));
+        assert!(imgs[1].is_opaque_at(1, 0));
+        assert_eq!(imgs[2].size(), Vec2::new(1., 3.));
+        assert!(imgs[2].is_opaque_at(0, 2));
+    }
+
+    #[test]
+    fn uv_bounds_is_full_texture() {
+        let ctx = Context::default();
+        let img = Img::from_img("test", ColorImage { size: [2, 1], pixels: vec![B, T] }, &ctx);
+        assert_eq!(img.uv_bounds(), Rect::from_min_max(Pos2::ZERO, Pos2::new(1., 1.)));
+    }
+
+    #[test]
+    fn can_find_holes() {
+        // ______
+        // _OOOO_
+        // _O__O_
+        // _OOOO_
+        // ______
+        let mut bits = BitSet::with_capacity(30);
+        for i in [7, 8, 9, 10, 13, 16, 19, 20, 21, 22] {
+            bits.insert(i);
+        }
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(6., 5.));
+        let ring = BitImg::new(Pixels2D::new(bits.clone(), rect));
+        assert_eq!(ring.holes(Connectivity::Four), vec![Rect::from_min_size(Pos2::new(2., 2.), Vec2::new(2., 1.))]);
+        assert_eq!(ring.holes(Connectivity::Eight), vec![Rect::from_min_size(Pos2::new(2., 2.), Vec2::new(2., 1.))]);
+
+        // Removing a corner of the ring opens the hole diagonally.
+        bits.remove(22);
+        let open_ring = BitImg::new(Pixels2D::new(bits, rect));
+        assert_eq!(open_ring.holes(Connectivity::Four).len(), 1);
+        assert!(open_ring.holes(Connectivity::Eight).is_empty());
+    }
+
+    #[test]
+    fn svg_with_stub_rasterizer() {
+        let img: ColorImage = load_svg_bytes_with(TEST_SVG, 1.0, &BoundingBoxRasterizer).unwrap();
+        assert_eq!(img.size, [100, 100]);
+        assert_eq!(img[(0, 0)], B);
+        assert_eq!(img[(98, 98)], B);
+        assert_eq!(img[(99, 0)], T);
+        assert_eq!(img[(0, 99)], T);
+
+        let img: ColorImage = load_svg_bytes_with(TEST_SVG, 0.5, &BoundingBoxRasterizer).unwrap();
+        assert_eq!(img.size, [50, 50]);
+        assert_eq!(img[(48, 48)], B);
+        assert_eq!(img[(49, 49)], T);
+    }
+
+    #[test]
+    fn can_center_on_canvas() {
+        // O_
+        // _O
+        let mut bits = BitSet::with_capacity(4);
+        bits.insert(0);
+        bits.insert(3);
+        let mut img = BitImg::new(Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(2., 2.))));
+        img.set_out_of_bounds(OutOfBounds::Clamp);
+
+        let centered = img.centered_on_canvas(Vec2::new(6., 5.)).unwrap();
+        assert_eq!(centered.out_of_bounds(), OutOfBounds::Clamp);
+        assert_eq!(centered.row_histogram().len(), 5);
+        assert_eq!(centered.column_histogram().len(), 6);
+        assert!(centered.is_opaque_at(2, 1));
+        assert!(centered.is_opaque_at(3, 2));
+        assert!(!centered.is_opaque_at(0, 0));
+        assert_eq!(centered.row_histogram().iter().sum::<usize>(), 2);
+        assert!(centered.contains_pixel(&Rect::from_min_size(Pos2::new(2., 1.), Vec2::new(1., 1.))));
+
+        assert!(img.centered_on_canvas(Vec2::new(2., 2.)).is_some());
+        assert!(img.centered_on_canvas(Vec2::new(1., 5.)).is_none());
+    }
+
+    #[test]
+    fn solid_pixel_rejects_isolated_specks() {
+        // OO___O
+        // OO____
+        // ______
+        let mut bits = BitSet::with_capacity(18);
+        for i in [0, 1, 5, 6, 7] {
+            bits.insert(i);
+        }
+        let img = BitImg::new(Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(6., 3.))));
+
+        let speck = Rect::from_min_size(Pos2::new(4., 0.), Vec2::new(2., 2.));
+        assert!(img.contains_pixel(&speck));
+        assert!(!img.contains_solid_pixel(&speck, 3));
+        assert!(img.contains_solid_pixel(&speck, 0));
+
+        let blob = Rect::from_min_size(Pos2::new(1., 1.), Vec2::new(1., 1.));
+        assert!(img.contains_solid_pixel(&blob, 3));
+        assert!(!img.contains_solid_pixel(&blob, 4));
+        assert!(img.contains_solid_pixel(&Rect::from_min_size(Pos2::new(-1., -1.), Vec2::new(10., 10.)), 3));
+
+        // Clamped onto the top left pixel, the same region as contains_pixel.
+        let mut img = img;
+        let outside = Rect::from_min_size(Pos2::new(-5., -5.), Vec2::new(2., 2.));
+        assert!(!img.contains_solid_pixel(&outside, 3));
+        img.set_out_of_bounds(OutOfBounds::Clamp);
+        assert!(img.contains_pixel(&outside));
+        assert!(img.contains_solid_pixel(&outside, 3));
+        assert!(!img.contains_solid_pixel(&outside, 4));
+    }
+
+    #[test]
+    fn out_of_bounds_policies() {
+        // ___
+        // __O
+        let mut bits = BitSet::with_capacity(6);
+        bits.insert(5);
+        let mut img = BitImg::new(Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(3., 2.))));
+        let on_border = Rect::from_min_size(Pos2::new(2., 1.), Vec2::new(1., 1.));
+        let past_border = Rect::from_min_size(Pos2::new(3., 2.), Vec2::new(1., 1.));
+
+        assert_eq!(img.out_of_bounds(), OutOfBounds::ReturnFalse);
+        assert!(img.is_opaque_at(2, 1));
+        assert!(!img.is_opaque_at(3, 1));
+        assert!(!img.is_opaque_at(0, 2));
+        assert!(img.opaque_at_point(Pos2::new(2.5, 1.5)));
+        assert!(!img.opaque_at_point(Pos2::new(3., 1.5)));
+        assert!(img.contains_pixel(&on_border));
+        assert!(!img.contains_pixel(&past_border));
+
+        img.set_out_of_bounds(OutOfBounds::Clamp);
+        assert!(img.is_opaque_at(2, 1));
+        assert!(img.is_opaque_at(3, 1));
+        assert!(img.is_opaque_at(3, 2));
+        assert!(!img.is_opaque_at(0, 2));
+        assert!(img.opaque_at_point(Pos2::new(3., 2.)));
+        assert!(!img.opaque_at_point(Pos2::new(-1., 2.)));
+        assert!(img.contains_pixel(&on_border));
+        assert!(img.contains_pixel(&past_border));
+        assert!(!img.contains_pixel(&Rect::from_min_size(Pos2::new(-5., -5.), Vec2::new(1., 1.))));
+
+        img.set_out_of_bounds(OutOfBounds::Panic);
+        assert!(img.is_opaque_at(2, 1));
+        assert!(img.opaque_at_point(Pos2::new(2.5, 1.5)));
+        assert!(img.contains_pixel(&on_border));
+        assert!(std::panic::catch_unwind(|| img.is_opaque_at(3, 1)).is_err());
+        assert!(std::panic::catch_unwind(|| img.opaque_at_point(Pos2::new(3., 1.))).is_err());
+        assert!(std::panic::catch_unwind(|| img.contains_pixel(&past_border)).is_err());
+    }
+
+    #[test]
+    fn can_export_tree_as_dot() {
+        // O__O
+        // __O_
+        // OOO_
+        let mut bit_set = BitSet::with_capacity(12);
+        for i in [0, 3, 6, 8, 9, 10] {
+            bit_set.insert(i);
+        }
+        let img = BitImg::new(Pixels2D::new(bit_set, Rect::from_min_size(Pos2::ZERO, Vec2::new(4., 3.))));
+
+        assert_eq!(
+            img.to_dot().unwrap(),
+            r#"digraph LayeredRect {
+    n0 [label="(0, 0) 4x3\npixel_count=6", shape=ellipse];
+    n1 [label="(0, 0) 2x3\npixel_count=3", shape=box, style=filled, fillcolor=gray];
+    n0 -> n1;
+    n2 [label="(2, 0) 2x3\npixel_count=3", shape=box, style=filled, fillcolor=gray];
+    n0 -> n2;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn svg_with_stylesheet() {
+        const STROKE_SVG: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10">
+           <path d="M 0 5 L 10 5" stroke="black" stroke-width="4"/>
+        </svg>
+        "#;
+        let img = load_svg_bytes(STROKE_SVG, 1.0).unwrap();
+        assert_eq!(img[(5, 5)], Color32::BLACK);
+
+        let img = load_svg_bytes_styled(STROKE_SVG, 1.0, "path { stroke: blue }").unwrap();
+        assert_eq!(img[(5, 5)], Color32::BLUE);
+        assert_eq!(img[(5, 0)], T);
+    }
+
+    #[test]
+    fn capsule_hit_test() {
+        let mut bits = BitSet::with_capacity(400);
+        // Single opaque pixel at (15, 4) on a 20x20 image.
+        bits.insert(15 + 4 * 20);
+        let img = BitImg::new(Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(20., 20.))));
+
+        // Pixel center is (15.5, 4.5).
+        assert!(img.capsule_hits_opaque(Pos2::new(0., 4.5), Pos2::new(20., 4.5), 0.));
+        assert!(img.capsule_hits_opaque(Pos2::new(0., 2.5), Pos2::new(20., 2.5), 2.));
+        assert!(!img.capsule_hits_opaque(Pos2::new(0., 2.5), Pos2::new(20., 2.5), 1.9));
+        assert!(!img.capsule_hits_opaque(Pos2::new(0., 4.5), Pos2::new(13., 4.5), 2.));
+        assert!(img.capsule_hits_opaque(Pos2::new(0., 4.5), Pos2::new(13.5, 4.5), 2.));
+        // Degenerated to a circle.
+        assert!(img.capsule_hits_opaque(Pos2::new(18.5, 8.5), Pos2::new(18.5, 8.5), 5.));
+        assert!(!img.capsule_hits_opaque(Pos2::new(18.5, 8.5), Pos2::new(18.5, 8.5), 4.9));
+        // Long capsule far from the image.
+        assert!(!img.capsule_hits_opaque(Pos2::new(-1000., -50.), Pos2::new(1000., -50.), 10.));
+    }
+
+    #[test]
+    fn memory_cache_evicts_least_recently_used() {
+        const SVG_A: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10"><rect width="5" height="5"/></svg>"#;
+        const SVG_B: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10"><rect width="6" height="6"/></svg>"#;
+        const SVG_C: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10"><rect width="7" height="7"/></svg>"#;
+        let dir = "clickable_img_memory_cache_test";
+        clear_cache(dir);
+        let loader = SvgLoader::new(1.0, Some(dir));
+        loader.set_memory_cache_max_entries(Some(2));
+
+        loader.load(SVG_A).unwrap();
+        loader.load(SVG_B).unwrap();
+        loader.load(SVG_A).unwrap();
+        assert_eq!(loader.renders.load(Ordering::Relaxed), 2);
+        assert_eq!(loader.memory_cache_len(), 2);
+
+        // B is the least recently used one.
+        loader.load(SVG_C).unwrap();
+        assert_eq!(loader.memory_cache_len(), 2);
+        assert_eq!(loader.renders.load(Ordering::Relaxed), 3);
+        loader.load(SVG_A).unwrap();
+        assert_eq!(loader.renders.load(Ordering::Relaxed), 3);
+
+        // Evicted from memory but served from disk.
+        let img = loader.load(SVG_B).unwrap();
+        assert_eq!(loader.renders.load(Ordering::Relaxed), 3);
+        assert_eq!(img, load_svg_bytes(SVG_B, 1.0).unwrap());
+
+        // 10x10 images are 400 bytes each.
+        loader.set_memory_cache_max_entries(None);
+        loader.set_memory_cache_max_bytes(Some(400));
+        assert_eq!(loader.memory_cache_len(), 1);
+        loader.memory_cache_clear();
+        assert_eq!(loader.memory_cache_len(), 0);
+        clear_cache(dir);
+    }
+
+    #[test]
+    fn pixels2d_total_opaque() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(5., 5.));
+        let pixels = sample_5x5_pixels();
+        assert_eq!(pixels.total_opaque(), 9);
+        assert_eq!(pixels.total_opaque(), pixels.pixel_count(rect));
+
+        let pixels = Pixels2D::new(BitSet::new(), rect);
+        assert_eq!(pixels.total_opaque(), 0);
+        assert_eq!(pixels.total_opaque(), pixels.pixel_count(rect));
+    }
+
+    #[test]
+    fn is_opaque_at_does_not_wrap_rows() {
+        // ________
+        // O_______
+        let img = ColorImage { size: [8, 2], pixels: vec![T, T, T, T, T, T, T, T, B, T, T, T, T, T, T, T] };
+        let ctx = Context::default();
+        let img = Img::from_img("test", img, &ctx);
+        assert!(img.is_opaque_at(0, 1));
+        assert!(!img.is_opaque_at(8, 0));
+        assert!(!img.is_opaque_at(0, 2));
+
+        let mut bits = BitSet::with_capacity(16);
+        bits.insert(8);
+        let pixels = Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(8., 2.)));
+        assert!(pixels.pixel_at(0, 1));
+        assert!(!pixels.pixel_at(8, 0));
+    }
+
+    #[test]
+    fn is_opaque_at_pos() {
+        // O_
+        // _O
+        let img = ColorImage { size: [2, 2], pixels: vec![B, T, T, B] };
+        let ctx = Context::default();
+        let img = Img::from_img("test", img, &ctx);
+        assert!(img.is_opaque_at_pos(Pos2::new(0., 0.)));
+        assert!(img.is_opaque_at_pos(Pos2::new(0.99, 0.99)));
+        assert!(!img.is_opaque_at_pos(Pos2::new(1., 0.5)));
+        assert!(img.is_opaque_at_pos(Pos2::new(1.5, 1.5)));
+        assert!(!img.is_opaque_at_pos(Pos2::new(-0.5, 0.5)));
+        assert!(!img.is_opaque_at_pos(Pos2::new(0.5, -100.)));
+        assert!(!img.is_opaque_at_pos(Pos2::new(2., 1.5)));
+        assert!(!img.is_opaque_at_pos(Pos2::new(f32::NAN, 0.5)));
+    }
+
+    #[test]
+    fn contains_pixel_with_negative_origin() {
+        // _____
+        // _____
+        // _____
+        // _____
+        // ____O
+        let mut bitset = BitSet::with_capacity(25);
+        bitset.insert(24);
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(5., 5.));
+        let pixels = Pixels2D::new(bitset, rect);
+        let img = BitImg::new(pixels.clone());
+
+        // Overlaps the whole image.
+        let query = Rect::from_min_size(Pos2::new(-5.0, -5.0), Vec2::new(10.0, 10.0));
+        assert!(pixels.contains_pixel(&query));
+        assert!(img.contains_pixel(&query));
+
+        // Overlaps (0, 0) - (2, 2) only.
+        let query = Rect::from_min_size(Pos2::new(-5.0, -5.0), Vec2::new(8.0, 8.0));
+        assert!(!pixels.contains_pixel(&query));
+        assert!(!img.contains_pixel(&query));
+        let query = Rect::from_min_size(Pos2::new(-0.0001, -0.0001), Vec2::new(4.0, 4.0));
+        assert!(!pixels.contains_pixel(&query));
+        assert!(!img.contains_pixel(&query));
+
+        let query = Rect::from_min_size(Pos2::new(-15.0, -15.0), Vec2::new(10.0, 10.0));
+        assert!(!pixels.contains_pixel(&query));
+        assert!(!img.contains_pixel(&query));
+    }
+
+    #[test]
+    fn pixel_count_ignores_outside_of_image() {
+        let pixels = sample_5x5_pixels();
+
+        let hanging = Rect::from_min_size(Pos2::new(2., 1.), Vec2::new(10., 10.));
+        let overlap = Rect::from_min_size(Pos2::new(2., 1.), Vec2::new(3., 4.));
+        assert_eq!(pixels.pixel_count(overlap), 4);
+        assert_eq!(pixels.pixel_count(hanging), 4);
+        assert_eq!(pixels.pixel_count(Rect::from_min_size(Pos2::new(-3., -3.), Vec2::new(20., 20.))), 9);
+        assert_eq!(pixels.pixel_count(Rect::from_min_size(Pos2::new(6., 0.), Vec2::new(2., 2.))), 0);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn can_load_png_from_path() {
+        let dir = std::env::temp_dir().join("clickable_img_from_path_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let png = dir.join("test.png");
+        let mut buf = image::RgbaImage::new(2, 1);
+        buf.put_pixel(1, 0, image::Rgba([255, 0, 0, 255]));
+        buf.save(&png).unwrap();
+
+        let ctx = Context::default();
+        let img = Img::from_path("test", &png, &ctx).unwrap();
+        assert_eq!(img.size(), Vec2::new(2., 1.));
+        assert!(!img.is_opaque_at(0, 0));
+        assert!(img.is_opaque_at(1, 0));
+
+        match Img::from_path("test", dir.join("missing.png"), &ctx) {
+            Err(crate::ImageLoadError::NotFound(_)) => {},
+            _ => panic!("Test failed."),
+        }
+        let broken = dir.join("broken.png");
+        std::fs::write(&broken, b"not a png").unwrap();
+        match Img::from_path("test", &broken, &ctx) {
+            Err(crate::ImageLoadError::DecodeFailed(_)) | Err(crate::ImageLoadError::UnsupportedFormat(_)) => {},
+            _ => panic!("Test failed."),
+        }
+
+        fn load(path: &std::path::Path, ctx: &Context) -> Result<Img, Box<dyn std::error::Error>> {
+            Ok(Img::from_path("test", path, ctx)?)
+        }
+        let err = load(&dir.join("missing.png"), &ctx).err().unwrap();
+        assert!(err.to_string().starts_with("Image file not found: "));
+        assert!(err.source().is_some());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn img_from_rgba() {
+        let ctx = Context::default();
+        let rgba = [0, 0, 0, 0, 255, 0, 0, 255];
+        let img = Img::from_rgba("test", 2, 1, &rgba, &ctx).unwrap();
+        assert_eq!(img.size(), Vec2::new(2., 1.));
+        assert!(!img.is_opaque_at(0, 0));
+        assert!(img.is_opaque_at(1, 0));
+
+        assert_eq!(
+            Img::from_rgba("test", 2, 2, &rgba, &ctx).err(),
+            Some(RgbaError::LengthMismatch { width: 2, height: 2, actual: 8 })
+        );
+    }
+
+    #[test]
+    fn img_keeps_name() {
+        let ctx = Context::default();
+        let img = Img::from_img("my icon", ColorImage { size: [1, 1], pixels: vec![B] }, &ctx);
+        assert_eq!(img.name(), "my icon");
+        let img = Img::from_svg(String::from("svg icon"), TEST_SVG, 0.1, &ctx).unwrap();
+        assert_eq!(img.name(), "svg icon");
+
+        let mut batch = ImgBatch::new();
+        batch.add("batched", ColorImage { size: [1, 1], pixels: vec![B] });
+        assert_eq!(batch.commit(&ctx)[0].name(), "batched");
+    }
+
+    #[test]
+    fn can_choose_texture_options() {
+        let ctx = Context::default();
+        let options_of = |img: &Img| ctx.tex_manager().read().meta(img.texture_id()).unwrap().options;
+
+        let img = Img::from_img("linear", ColorImage { size: [1, 1], pixels: vec![B] }, &ctx);
+        assert_eq!(options_of(&img), TextureOptions::LINEAR);
+        let img = Img::from_img_with_options("nearest", ColorImage { size: [1, 1], pixels: vec![B] }, TextureOptions::NEAREST, &ctx);
+        assert_eq!(options_of(&img), TextureOptions::NEAREST);
+        assert!(img.is_opaque_at(0, 0));
+        let img = Img::from_svg_with_options("svg", TEST_SVG, 0.1, TextureOptions::NEAREST_REPEAT, &ctx).unwrap();
+        assert_eq!(options_of(&img), TextureOptions::NEAREST_REPEAT);
+    }
+
+    #[test]
+    fn can_compute_opaque_bounds() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(8., 16.));
+        let mut bits = BitSet::with_capacity(128);
+        bits.insert(6 + 2 * 8);
+        let img = BitImg::new(Pixels2D::new(bits.clone(), rect));
+        assert_eq!(img.opaque_bounds(), Some(Rect::from_min_size(Pos2::new(6., 2.), Vec2::new(1., 1.))));
+
+        bits.insert(1 + 14 * 8);
+        bits.insert(7 + 12 * 8);
+        let img = BitImg::new(Pixels2D::new(bits, rect));
+        assert_eq!(img.opaque_bounds(), Some(Rect::from_min_max(Pos2::new(1., 2.), Pos2::new(8., 15.))));
+
+        let img = BitImg::new(Pixels2D::new(BitSet::new(), rect));
+        assert_eq!(img.opaque_bounds(), None);
+
+        let ctx = Context::default();
+        let img = Img::from_img("test", ColorImage { size: [3, 2], pixels: vec![T, T, T, T, B, T] }, &ctx);
+        assert_eq!(img.opaque_bounds(), Some(Rect::from_min_size(Pos2::new(1., 1.), Vec2::new(1., 1.))));
+    }
+
+    #[test]
+    fn can_compute_opaque_ratio() {
+        let mut bits = BitSet::with_capacity(16);
+        // Left half of a 4x4 image is opaque.
+        for y in 0..4 {
+            bits.insert(y * 4);
+            bits.insert(1 + y * 4);
+        }
+        let img = BitImg::new(Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(4., 4.))));
+
+        assert_eq!(img.opaque_ratio(&Rect::from_min_size(Pos2::ZERO, Vec2::new(4., 4.))), 0.5);
+        assert_eq!(img.opaque_ratio(&Rect::from_min_size(Pos2::ZERO, Vec2::new(2., 4.))), 1.0);
+        assert_eq!(img.opaque_ratio(&Rect::from_min_size(Pos2::new(1., 0.), Vec2::new(2., 2.))), 0.5);
+        assert_eq!(img.opaque_ratio(&Rect::from_min_size(Pos2::new(2., 0.), Vec2::new(2., 4.))), 0.0);
+        // Only the part inside of the image counts.
+        assert_eq!(img.opaque_ratio(&Rect::from_min_size(Pos2::new(-2., -2.), Vec2::new(4., 4.))), 1.0);
+        assert_eq!(img.opaque_ratio(&Rect::from_min_size(Pos2::new(10., 10.), Vec2::new(4., 4.))), 0.0);
+    }
+
+    #[test]
+    fn can_find_first_opaque_pixel() {
+        let img = sample_5x5();
+
+        assert_eq!(img.first_opaque_in(&Rect::from_min_size(Pos2::ZERO, Vec2::new(5., 5.))), Some(Pos2::new(0., 0.)));
+        assert_eq!(img.first_opaque_in(&Rect::from_min_size(Pos2::new(3., 0.), Vec2::new(2., 2.))), Some(Pos2::new(4., 0.)));
+        assert_eq!(img.first_opaque_in(&Rect::from_min_size(Pos2::new(0., 3.), Vec2::new(3., 2.))), Some(Pos2::new(1., 3.)));
+        // Row 1 has (2, 1) while the left columns start at row 2.
+        assert_eq!(img.first_opaque_in(&Rect::from_min_size(Pos2::new(0., 1.), Vec2::new(5., 4.))), Some(Pos2::new(2., 1.)));
+        assert_eq!(img.first_opaque_in(&Rect::from_min_size(Pos2::new(3., 2.), Vec2::new(2., 3.))), None);
+        assert_eq!(img.first_opaque_in(&Rect::from_min_size(Pos2::new(10., 10.), Vec2::new(2., 2.))), None);
+    }
+
+    #[test]
+    fn circle_hit_test() {
+        let mut bits = BitSet::with_capacity(100);
+        // Opaque corners of a 10x10 image.
+        for (x, y) in [(0, 0), (9, 0), (0, 9), (9, 9)] {
+            bits.insert(x + y * 10);
+        }
+        let img = BitImg::new(Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(10., 10.))));
+
+        // The inscribed circle misses the corners although its bounding square covers them.
+        assert!(!img.contains_pixel_in_circle(Pos2::new(5., 5.), 5.));
+        assert!(img.contains_pixel(&Rect::from_center_size(Pos2::new(5., 5.), Vec2::new(10., 10.))));
+        // Corner pixel centers are sqrt(40.5) ~ 6.36 away.
+        assert!(!img.contains_pixel_in_circle(Pos2::new(5., 5.), 6.3));
+        assert!(img.contains_pixel_in_circle(Pos2::new(5., 5.), 6.4));
+        assert!(img.contains_pixel_in_circle(Pos2::new(9.5, 9.5), 0.));
+        assert!(!img.contains_pixel_in_circle(Pos2::new(-20., -20.), 5.));
+        assert!(!img.contains_pixel_in_circle(Pos2::new(0.5, 0.5), -1.));
+    }
+
+    #[test]
+    fn polygon_hit_test() {
+        let mut bits = BitSet::with_capacity(100);
+        // Single opaque pixel at (5, 5) on a 10x10 image, its center is (5.5, 5.5).
+        bits.insert(5 + 5 * 10);
+        let img = BitImg::new(Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(10., 10.))));
+
+        let triangle = [Pos2::new(0., 0.), Pos2::new(10., 0.), Pos2::new(0., 10.)];
+        assert!(!img.contains_pixel_in_polygon(&triangle));
+        let triangle = [Pos2::new(0., 0.), Pos2::new(12., 0.), Pos2::new(0., 12.)];
+        assert!(img.contains_pixel_in_polygon(&triangle));
+
+        assert!(!img.contains_pixel_in_polygon(&[]));
+        assert!(!img.contains_pixel_in_polygon(&[Pos2::new(0., 0.), Pos2::new(10., 10.)]));
+        assert!(!img.contains_pixel_in_polygon(&[Pos2::new(20., 20.), Pos2::new(30., 20.), Pos2::new(20., 30.)]));
+
+        // A pentagram: its center is covered twice and is therefore outside by the even-odd rule.
+        let star: Vec<Pos2> = (0..5).map(|i| {
+            let angle = std::f32::consts::PI * (-0.5 + 0.8 * i as f32);
+            Pos2::new(5.5 + 5. * angle.cos(), 5.5 + 5. * angle.sin())
+        }).collect();
+        assert!(!img.contains_pixel_in_polygon(&star));
+        // Moved down so that the pixel lies in the top arm, covered once.
+        let shifted: Vec<Pos2> = star.iter().map(|p| *p + Vec2::new(0., 3.5)).collect();
+        assert!(img.contains_pixel_in_polygon(&shifted));
+    }
+
+    #[test]
+    fn segment_hit_test() {
+        let mut bits = BitSet::with_capacity(200);
+        // Row 5 of a 20x10 image is opaque from x = 8 to 11.
+        for x in 8..12 {
+            bits.insert(x + 5 * 20);
+        }
+        let img = BitImg::new(Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(20., 10.))));
+
+        assert!(img.intersects_segment(Pos2::new(0., 5.5), Pos2::new(20., 5.5)));
+        assert!(img.intersects_segment(Pos2::new(-100., 5.5), Pos2::new(100., 5.5)));
+        assert!(!img.intersects_segment(Pos2::new(0., 4.5), Pos2::new(20., 4.5)));
+        assert!(!img.intersects_segment(Pos2::new(0., 5.5), Pos2::new(7.9, 5.5)));
+        assert!(img.intersects_segment(Pos2::new(9.5, 0.), Pos2::new(9.5, 10.)));
+        assert!(img.intersects_segment(Pos2::new(5., 0.), Pos2::new(15., 10.)));
+        assert!(!img.intersects_segment(Pos2::new(0., 0.), Pos2::new(4., 10.)));
+        assert!(img.intersects_segment(Pos2::new(10.5, 5.5), Pos2::new(10.5, 5.5)));
+        assert!(!img.intersects_segment(Pos2::new(-10., -10.), Pos2::new(30., -10.)));
+    }
+
+    #[test]
+    fn can_read_total_opaque() {
+        let mut bits = BitSet::with_capacity(128);
+        for i in [0, 5, 17, 18, 63, 64, 100, 127] {
+            bits.insert(i);
+        }
+        let expected = bits.get_ref().count_ones() as usize;
+        let img = BitImg::new(Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(8., 16.))));
+        assert_eq!(img.total_opaque(), expected);
+        assert_eq!(img.total_opaque(), 8);
+
+        let ctx = Context::default();
+        let img = Img::from_img("test", ColorImage { size: [3, 2], pixels: vec![B, T, B, T, B, T] }, &ctx);
+        assert_eq!(img.total_opaque(), 3);
+    }
+
+    #[test]
+    fn svg_error_is_std_error() {
+        fn load(bytes: &[u8]) -> Result<ColorImage, Box<dyn std::error::Error>> {
+            Ok(load_svg_bytes(bytes, 1.0)?)
+        }
+
+        let err = load(b"not an svg").unwrap_err();
+        assert!(err.to_string().starts_with("Cannot parse SVG: "));
+        assert!(err.source().is_some());
+
+        assert_eq!(SvgError::CannotLoad { width: 3, height: 4 }.to_string(), "Cannot allocate a 3x4 pixmap for SVG");
+        assert_eq!(SvgError::Other("boom".to_owned()).to_string(), "boom");
+        assert!(std::error::Error::source(&SvgError::CannotRender).is_none());
+    }
+
+    #[test]
+    fn svg_can_be_rendered_to_size() {
+        let img = load_svg_bytes_sized(TEST_SVG, 48, 48, AspectMode::Stretch).unwrap();
+        assert_eq!(img.size, [48, 48]);
+        assert_ne!(img[(0, 0)], T);
+
+        let stretched = load_svg_bytes_sized(TEST_SVG, 100, 50, AspectMode::Stretch).unwrap();
+        assert_eq!(stretched.size, [100, 50]);
+        assert_ne!(stretched[(0, 25)], T);
+        assert_ne!(stretched[(97, 25)], T);
+
+        // The square viewBox is 50x50 in the middle of a 100x50 image.
+        let letterboxed = load_svg_bytes_sized(TEST_SVG, 100, 50, AspectMode::Fit).unwrap();
+        assert_eq!(letterboxed.size, [100, 50]);
+        assert_eq!(letterboxed[(24, 25)], T);
+        assert_ne!(letterboxed[(26, 25)], T);
+        assert_ne!(letterboxed[(73, 25)], T);
+        assert_eq!(letterboxed[(75, 25)], T);
+
+        // A 2:1 viewBox scaled to cover 50x50 is 100x50, so only x in [50, 150) of the SVG is visible.
+        let wide = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 200 100">
+           <rect x="0" y="0" width="55" height="100" fill="red"/>
+           <rect x="60" y="0" width="140" height="100" fill="red"/>
+        </svg>"#;
+        let filled = load_svg_bytes_sized(wide, 50, 50, AspectMode::Fill).unwrap();
+        assert_eq!(filled.size, [50, 50]);
+        assert_ne!(filled[(0, 25)], T);
+        assert_eq!(filled[(3, 25)], T);
+        assert_ne!(filled[(6, 25)], T);
+        assert_ne!(filled[(49, 25)], T);
+
+        assert!(matches!(load_svg_bytes_sized(TEST_SVG, 0, 10, AspectMode::Fit), Err(SvgError::CannotLoad { width: 0, height: 10 })));
+    }
+
+    #[test]
+    fn svg_can_be_loaded_with_options() {
+        let mut fontdb = usvg::fontdb::Database::new();
+        fontdb.load_system_fonts();
+        // Whatever fonts the system has, text falls back to one of them.
+        let font_family = fontdb.faces().next().expect("no system fonts").families[0].0.clone();
+        let opt = usvg::Options {
+            fontdb: std::sync::Arc::new(fontdb),
+            font_family,
+            ..usvg::Options::default()
+        };
+        let img = load_svg_bytes_with_options(TEST_SVG, 0.5, &opt).unwrap();
+        assert_eq!(img.size, [50, 50]);
+        assert_ne!(img[(10, 10)], T);
+
+        // Text is only drawn with the fonts of the options.
+        const TEXT_SVG: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 40">
+          <text x="5" y="30" font-size="30">Text</text>
+        </svg>"#;
+        let img = load_svg_bytes_with_options(TEXT_SVG, 1.0, &opt).unwrap();
+        assert!(img.pixels.iter().any(|c| *c != T));
+        let img = load_svg_bytes_with_options(TEXT_SVG, 1.0, &usvg::Options::default()).unwrap();
+        assert!(img.pixels.iter().all(|c| *c == T));
+
+        // The options are not ignored.
+        let opt = usvg::Options {
+            style_sheet: Some("rect { display: none }".to_owned()),
+            ..usvg::Options::default()
+        };
+        let img = load_svg_bytes_with_options(TEST_SVG, 0.5, &opt).unwrap();
+        assert_eq!(img[(10, 10)], T);
+    }
+
+    #[test]
+    fn svg_can_be_rendered_over_background() {
+        // TEST_SVG leaves the last row and column of its 100x100 viewBox uncovered.
+        let img = load_svg_bytes_with_background(TEST_SVG, 1.0, None).unwrap();
+        assert_eq!(img[(99, 99)], T);
+
+        let img = load_svg_bytes_with_background(TEST_SVG, 1.0, Some(Color32::WHITE)).unwrap();
+        assert_eq!(img[(99, 99)], Color32::WHITE);
+        assert_eq!(img[(50, 50)], Color32::from_rgb(255, 0, 0));
+
+        let ctx = Context::default();
+        let img = Img::from_img("tile", img, &ctx);
+        assert!(img.contains_pixel(&Rect::from_min_size(Pos2::new(99., 99.), Vec2::new(1., 1.))));
+    }
+
+    #[test]
+    fn oversized_svg_output_is_rejected() {
+        let tiny = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 2 1"><rect width="2" height="1"/></svg>"#;
+        assert!(matches!(load_svg_bytes(tiny, 1e6), Err(SvgError::TooLarge { width: 2_000_000, height: 1_000_000 })));
+        assert!(matches!(load_svg_bytes(tiny, f32::INFINITY), Err(SvgError::TooLarge { width: u32::MAX, height: u32::MAX })));
+        assert!(load_svg_bytes(tiny, 100.).is_ok());
+
+        let rasterizer = ResvgRasterizer { max_dimension: 100 };
+        assert!(matches!(load_svg_bytes_with(tiny, 51., &rasterizer), Err(SvgError::TooLarge { width: 102, height: 51 })));
+        assert!(load_svg_bytes_with(tiny, 50., &rasterizer).is_ok());
+
+        assert!(matches!(load_svg_bytes_sized(tiny, 20000, 10, AspectMode::Fit), Err(SvgError::TooLarge { .. })));
+    }
+
+    #[test]
+    fn can_read_svg_intrinsic_size() {
+        assert_eq!(svg_intrinsic_size(TEST_SVG).unwrap(), Vec2::new(100., 100.));
+        let wide = br#"<svg xmlns="http://www.w3.org/2000/svg" width="48" height="24" viewBox="0 0 2 1"/>"#;
+        assert_eq!(svg_intrinsic_size(wide).unwrap(), Vec2::new(48., 24.));
+        assert!(matches!(svg_intrinsic_size(b"not an svg"), Err(SvgError::CannotParse(_))));
+    }
+
+    #[test]
+    fn svg_loader_can_be_shared_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SvgLoader>();
+
+        let dir = "clickable_img_thread_test";
+        clear_cache(dir);
+        let loader = std::sync::Arc::new(SvgLoader::new(0.5, Some(dir)));
+        let handles: Vec<_> = (0..8).map(|_| {
+            let loader = loader.clone();
+            std::thread::spawn(move || loader.load(TEST_SVG).unwrap())
+        }).collect();
+        let images: Vec<ColorImage> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        let expected = load_svg_bytes(TEST_SVG, 0.5).unwrap();
+        for img in images {
+            assert_eq!(img, expected);
+        }
+        loader.memory_cache_clear();
+        assert_eq!(loader.load(TEST_SVG).unwrap(), expected);
+        assert_eq!(loader.cache_dir(), Some(std::path::Path::new(dir)));
+    }
+
+    #[test]
+    fn svg_loader_accepts_absolute_cache_dir() {
+        let dir = std::env::temp_dir().join("clickable_img_absolute_dir_test");
+        clear_cache(&dir);
+        let loader = SvgLoader::new(0.2, Some(&dir)).with_namespace("icons").with_prefix("p");
+        let expected = loader.load(TEST_SVG).unwrap();
+        let path = dir.join("icons").join(format!("p_{}", cache_key(0.2, TEST_SVG)));
+        assert!(path.exists());
+
+        let loader = SvgLoader::new(0.2, Some(&dir)).with_namespace("icons").with_prefix("p");
+        assert_eq!(loader.load(TEST_SVG).unwrap(), expected);
+        assert_eq!(loader.cache_stats(), CacheStats { hits: 1, misses: 0 });
+        loader.invalidate(TEST_SVG).unwrap();
+        assert!(!path.exists());
+        clear_cache(&dir);
+    }
+
+    #[test]
+    fn img_cache_keeps_textures() {
+        const SVG_A: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10"><rect width="5" height="5"/></svg>"#;
+        const SVG_B: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10"><rect width="6" height="6"/></svg>"#;
+        let ctx = Context::default();
+        let mut cache = ImgCache::new(SvgLoader::new(1.0, None::<&str>), 2);
+
+        let a = cache.get_or_load("a", SVG_A, &ctx).unwrap().texture_id();
+        assert_eq!(cache.get_or_load("a", SVG_A, &ctx).unwrap().texture_id(), a);
+        let b = cache.get_or_load("b", SVG_B, &ctx).unwrap().texture_id();
+        assert_ne!(a, b);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.loader().renders.load(Ordering::Relaxed), 2);
+
+        // A becomes the most recently used, so shrinking drops B and its texture.
+        assert_eq!(cache.get_or_load("a", SVG_A, &ctx).unwrap().name(), "a");
+        cache.set_capacity(1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get_or_load("a", SVG_A, &ctx).unwrap().texture_id(), a);
+        assert!(ctx.tex_manager().read().meta(b).is_none());
+
+        assert!(cache.get_or_load("broken", b"not an svg", &ctx).is_err());
+        assert_eq!(cache.len(), 1);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn svg_loader_counts_disk_cache_hits() {
+        let dir = "clickable_img_cache_stats_test";
+        clear_cache(dir);
+        let loader = SvgLoader::new(0.2, Some(dir));
+        assert_eq!(loader.cache_stats(), CacheStats::default());
+
+        loader.load(TEST_SVG).unwrap();
+        assert_eq!(loader.cache_stats(), CacheStats { hits: 0, misses: 1 });
+        // Served from memory, not counted.
+        loader.load(TEST_SVG).unwrap();
+        assert_eq!(loader.cache_stats(), CacheStats { hits: 0, misses: 1 });
+
+        // A fresh loader finds the entry written by the first one, so the key is stable.
+        let loader = SvgLoader::new(0.2, Some(dir));
+        loader.load(TEST_SVG).unwrap();
+        assert_eq!(loader.cache_stats(), CacheStats { hits: 1, misses: 0 });
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn bitimg_serde_round_trip() {
+        let pixels = sample_5x5_pixels();
+        let json = serde_json::to_string(&pixels).unwrap();
+        assert_eq!(serde_json::from_str::<Pixels2D>(&json).unwrap(), pixels);
+        assert!(!serde_json::from_str::<Pixels2D>(&json).unwrap().has_summed_area_table());
+
+        // The summed-area table is rebuilt.
+        let json = serde_json::to_string(&pixels.clone().with_summed_area_table()).unwrap();
+        let restored = serde_json::from_str::<Pixels2D>(&json).unwrap();
+        assert!(restored.has_summed_area_table());
+        assert_eq!(restored.pixel_count(Rect::from_min_size(Pos2::ZERO, Vec2::new(3., 3.))), 5);
+        let img = BitImg::new(pixels.clone().with_summed_area_table());
+        let restored: BitImg = serde_json::from_str(&serde_json::to_string(&img).unwrap()).unwrap();
+        assert!(restored.pixels.has_summed_area_table());
+
+        let mut img = BitImg::new(pixels);
+        img.set_out_of_bounds(OutOfBounds::Clamp);
+        let restored: BitImg = serde_json::from_str(&serde_json::to_string(&img).unwrap()).unwrap();
+        assert_eq!(restored.out_of_bounds(), OutOfBounds::Clamp);
+        assert_eq!(restored.layered_rect, img.layered_rect);
+        for y in 0..6 {
+            for x in 0..6 {
+                for (w, h) in [(1., 1.), (2., 1.), (2., 3.)] {
+                    let rect = Rect::from_min_size(Pos2::new(x as f32, y as f32), Vec2::new(w, h));
+                    assert_eq!(restored.contains_pixel(&rect), img.contains_pixel(&rect));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bitimg_from_color_image() {
+        let img = BitImg::from_color_image(&ColorImage { size: [3, 2], pixels: vec![T, B, T, T, T, B] });
+        assert!(!img.is_opaque_at(0, 0));
+        assert!(img.is_opaque_at(1, 0));
+        assert!(img.is_opaque_at(2, 1));
+        assert_eq!(img.total_opaque(), 2);
+        assert!(img.contains_pixel(&Rect::from_min_size(Pos2::new(1., 0.), Vec2::new(1., 1.))));
+        assert!(!img.contains_pixel(&Rect::from_min_size(Pos2::new(0., 1.), Vec2::new(2., 1.))));
+    }
+
+    #[test]
+    fn can_render_mask_to_color_image() {
+        let mut bits = BitSet::with_capacity(128);
+        bits.insert(6 + 2 * 8);
+        bits.insert(1 + 14 * 8);
+        let img = BitImg::new(Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(8., 16.))));
+
+        let mask = img.mask_to_color_image(Color32::WHITE, Color32::BLACK);
+        assert_eq!(mask.size, [8, 16]);
+        assert_eq!(mask[(6, 2)], Color32::WHITE);
+        assert_eq!(mask[(1, 14)], Color32::WHITE);
+        assert_eq!(mask[(2, 6)], Color32::BLACK);
+        assert_eq!(mask.pixels.iter().filter(|c| **c == Color32::WHITE).count(), 2);
+    }
+
+    #[test]
+    fn can_write_ascii() {
+        let pixels = sample_5x5_pixels();
+        let mut ascii = String::new();
+        pixels.write_ascii(&mut ascii).unwrap();
+        assert_eq!(ascii, "X   X\n  XX \nXXX  \n XX  \n     \n");
+    }
+
+    #[test]
+    fn pixels2d_displays_as_ascii() {
+        let mut bitset = BitSet::with_capacity(6);
+        bitset.insert(0);
+        bitset.insert(5);
+        let pixels = Pixels2D::new(bitset, Rect::from_min_size(Pos2::ZERO, Vec2::new(3., 2.)));
+        assert_eq!(format!("{}", pixels), "X  \n  X");
+
+        let mut ascii = String::new();
+        pixels.write_ascii(&mut ascii).unwrap();
+        assert_eq!(ascii, format!("{}\n", pixels));
+
+        let empty = Pixels2D::new(BitSet::new(), Rect::from_min_size(Pos2::ZERO, Vec2::new(3., 0.)));
+        assert_eq!(empty.to_string(), "");
+    }
+
+    #[test]
+    fn can_build_tree_for_large_mask() {
+        let size = 4096;
+        let mut bits = BitSet::with_capacity(size * size);
+        bits.insert(0);
+        bits.insert(size * size - 1);
+        bits.insert(1234 + 2345 * size);
+        let img = BitImg::new(Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(size as f32, size as f32))));
+
+        assert_eq!(img.total_opaque(), 3);
+        assert!(img.contains_pixel(&Rect::from_min_size(Pos2::new(1230., 2340.), Vec2::new(10., 10.))));
+        assert!(!img.contains_pixel(&Rect::from_min_size(Pos2::new(1., 1.), Vec2::new(1000., 1000.))));
+        assert_eq!(img.opaque_bounds(), Some(Rect::from_min_size(Pos2::ZERO, Vec2::new(size as f32, size as f32))));
+    }
+
+    #[test]
+    fn min_node_size_is_configurable() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(16., 16.));
+        let mut bits = BitSet::with_capacity(256);
+        bits.insert(5 + 9 * 16);
+        let default = BitImg::new(Pixels2D::new(bits.clone(), rect));
+        let coarse = BitImg::with_min_node_size(Pixels2D::new(bits.clone(), rect), 8.);
+        let fine = BitImg::with_min_node_size(Pixels2D::new(bits, rect), 1.);
+
+        assert_eq!(default.layered_rect.depth(), 6);
+        assert_eq!(coarse.layered_rect.depth(), 2);
+        assert_eq!(coarse.leaves_intersecting(&rect).unwrap().len(), 4);
+        assert_eq!(fine.layered_rect.depth(), 8);
+        for img in [&default, &coarse, &fine] {
+            assert!(img.contains_pixel(&Rect::from_min_size(Pos2::new(5., 9.), Vec2::new(1., 1.))));
+            assert!(!img.contains_pixel(&Rect::from_min_size(Pos2::new(6., 9.), Vec2::new(10., 7.))));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn min_node_size_must_be_at_least_one() {
+        BitImg::with_min_node_size(Pixels2D::new(BitSet::new(), Rect::from_min_size(Pos2::ZERO, Vec2::new(4., 4.))), 0.5);
+    }
+
+    #[test]
+    fn summed_area_table_counts_like_scan() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(5., 5.));
+        let scan = sample_5x5_pixels();
+        let table = scan.clone().with_summed_area_table();
+        assert!(!scan.has_summed_area_table());
+        assert!(table.has_summed_area_table());
+        assert_eq!(scan, table);
+
+        for y in -1..6 {
+            for x in -1..6 {
+                for (w, h) in [(1., 1.), (2., 1.), (1., 3.), (3., 2.), (10., 10.)] {
+                    let query = Rect::from_min_size(Pos2::new(x as f32, y as f32), Vec2::new(w, h));
+                    assert_eq!(table.pixel_count(query), scan.pixel_count(query), "{:?}", query);
+                    assert_eq!(table.contains_pixel(&query), scan.contains_pixel(&query), "{:?}", query);
+                }
+            }
+        }
+        assert_eq!(table.pixel_count(rect), 9);
+
+        let img = BitImg::new(table);
+        assert_eq!(img.total_opaque(), 9);
+        assert!(img.contains_pixel(&Rect::from_min_size(Pos2::new(1., 3.), Vec2::new(1., 1.))));
+
+        // Built before the tree through the options.
+        let built = BitImg::with_options(scan.clone(), TreeOptions { summed_area_table: true, ..TreeOptions::default() });
+        assert!(built.pixels.has_summed_area_table());
+        assert_eq!(built.layered_rect, BitImg::new(scan).layered_rect);
+    }
+
+    #[test]
+    fn contains_pixel_scans_across_blocks() {
+        // Rows of 70 pixels straddle the 32 bit blocks of the bitset.
+        let (w, h) = (70, 5);
+        let mut bits = BitSet::with_capacity(w * h);
+        for (x, y) in [(31, 0), (32, 1), (63, 2), (64, 2), (0, 3), (69, 4)] {
+            bits.insert(x + y * w);
+        }
+        let pixels = Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(w as f32, h as f32)));
+
+        for y in 0..h {
+            for x0 in 0..w {
+                for x1 in (x0 + 1)..=w {
+                    let query = Rect::from_min_max(Pos2::new(x0 as f32, y as f32), Pos2::new(x1 as f32, (y + 1) as f32));
+                    let expected = (x0..x1).any(|x| pixels.pixel_at(x, y));
+                    assert_eq!(pixels.contains_pixel(&query), expected, "{:?}", query);
+                }
+            }
+        }
+        assert!(pixels.contains_pixel(&Rect::from_min_size(Pos2::new(30., 0.), Vec2::new(3., 2.))));
+        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(33., 0.), Vec2::new(30., 2.))));
+    }
+
+    #[test]
+    fn pixels2d_from_bitmap() {
+        let img = ColorImage { size: [3, 2], pixels: vec![T, B, T, T, T, B] };
+        let bitmap = to_bitmap(&img);
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), to_bitset(&img).iter().collect::<Vec<_>>());
+
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(3., 2.));
+        let pixels = Pixels2D::from_bitmap(bitmap, rect);
+        assert_eq!(pixels, Pixels2D::new(to_bitset(&img), rect));
+        assert!(pixels.pixel_at(1, 0));
+        assert!(!pixels.pixel_at(1, 1));
+        assert_eq!(pixels.total_opaque(), 2);
+
+        // Bits past the last pixel are dropped.
+        let mut bits = to_bitset(&img);
+        bits.insert(6);
+        assert_eq!(Pixels2D::new(bits, rect).total_opaque(), 2);
+    }
+
+    #[test]
+    fn lazy_tree_answers_like_eager_tree() {
+        let (w, h) = (64, 48);
+        let mut bits = BitSet::with_capacity(w * h);
+        for (x, y) in [(3, 4), (40, 10), (41, 10), (63, 47), (20, 30)] {
+            bits.insert(x + y * w);
+        }
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(w as f32, h as f32));
+        let eager = BitImg::new(Pixels2D::new(bits.clone(), rect));
+        let lazy = BitImg::with_options(Pixels2D::new(bits, rect), TreeOptions { lazy: true, ..TreeOptions::default() });
+
+        // Only the nodes along the descent are built.
+        assert!(lazy.contains_pixel(&Rect::from_min_size(Pos2::new(3., 4.), Vec2::new(1., 1.))));
+        let built = lazy.lazy.as_ref().unwrap().root.built_count();
+        assert!(built < 40, "{}", built);
+
+        for y in (0..h).step_by(3) {
+            for x in (0..w).step_by(3) {
+                for (qw, qh) in [(1., 1.), (4., 2.), (10., 10.)] {
+                    let query = Rect::from_min_size(Pos2::new(x as f32, y as f32), Vec2::new(qw, qh));
+                    assert_eq!(lazy.contains_pixel(&query), eager.contains_pixel(&query), "{:?}", query);
+                }
+            }
+        }
+        assert_eq!(lazy.total_opaque(), 5);
+        assert_eq!(lazy.opaque_bounds(), eager.opaque_bounds());
+
+        // The lazy tree is not reported as if it were a single leaf.
+        assert!(lazy.tree_stats().is_none());
+        assert!(lazy.to_dot().is_none());
+        assert!(lazy.tree_to_svg().is_none());
+        assert!(lazy.leaves_intersecting(&rect).is_none());
+        assert!(lazy.layered_rect().is_none());
+        assert!(eager.tree_stats().is_some());
+    }
+
+    #[test]
+    fn quadtree_answers_like_binary_tree() {
+        let quadtree = TreeOptions { split_strategy: SplitStrategy::Quadtree, ..TreeOptions::default() };
+        let binary = sample_5x5();
+        let quad = BitImg::with_options(sample_5x5_pixels(), quadtree.clone());
+        let lazy_quad = BitImg::with_options(sample_5x5_pixels(), TreeOptions { lazy: true, ..quadtree.clone() });
+
+        for y in -1..6 {
+            for x in -1..6 {
+                for (w, h) in [(1., 1.), (2., 1.), (1., 2.), (2., 2.), (3., 3.)] {
+                    let query = Rect::from_min_size(Pos2::new(x as f32, y as f32), Vec2::new(w, h));
+                    assert_eq!(quad.contains_pixel(&query), binary.contains_pixel(&query), "{:?}", query);
+                    assert_eq!(lazy_quad.contains_pixel(&query), binary.contains_pixel(&query), "{:?}", query);
+                }
+            }
+        }
+
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(16., 16.));
+        let binary = BitImg::new(Pixels2D::new(BitSet::new(), rect));
+        let quad = BitImg::with_options(Pixels2D::new(BitSet::new(), rect), quadtree);
+        assert_eq!(binary.layered_rect.depth(), 6);
+        assert_eq!(quad.layered_rect.depth(), 3);
+        match &quad.layered_rect {
+            LayeredRect::Node { rect: _, children, pixel_count: _ } => assert_eq!(children.len(), 4),
+            LayeredRect::Leaf { rect: _, pixel_count: _ } => panic!("Test failed."),
+        }
+        assert_eq!(quad.leaves_intersecting(&rect).unwrap().len(), 64);
+    }
+
+    #[test]
+    fn can_compute_tree_stats() {
+        let img = sample_5x5();
+
+        // Split into 2x5 and 3x5, then 2x2, 2x3, 3x2 and 3x3 leaves.
+        assert_eq!(img.tree_stats().unwrap(), TreeStats {
+            depth: 2,
+            node_count: 7,
+            leaf_count: 4,
+            empty_leaf_count: 0,
+            min_leaf_pixels: 1,
+            max_leaf_pixels: 3,
+            mean_leaf_pixels: 2.25,
+        });
+    }
+
+    #[test]
+    fn can_export_tree_as_svg() {
+        // O__O
+        // __O_
+        // OOO_
+        let mut bit_set = BitSet::with_capacity(12);
+        for i in [0, 3, 6, 8, 9, 10] {
+            bit_set.insert(i);
+        }
+        let img = BitImg::new(Pixels2D::new(bit_set, Rect::from_min_size(Pos2::ZERO, Vec2::new(4., 3.))));
+
+        assert_eq!(
+            img.tree_to_svg().unwrap(),
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="3" viewBox="0 0 4 3">
+  <rect x="0" y="0" width="4" height="3" fill="black" fill-opacity="0.5" stroke="red" stroke-width="0.1"/>
+  <rect x="0" y="0" width="2" height="3" fill="black" fill-opacity="0.5" stroke="red" stroke-width="0.1"/>
+  <rect x="2" y="0" width="2" height="3" fill="black" fill-opacity="0.5" stroke="red" stroke-width="0.1"/>
+</svg>
+"#
+        );
+        assert_eq!(svg_intrinsic_size(img.tree_to_svg().unwrap().as_bytes()).unwrap(), Vec2::new(4., 3.));
+    }
+
+    #[test]
+    fn can_flip_mask() {
+        let mut img = sample_5x5();
+        img.set_out_of_bounds(OutOfBounds::Clamp);
+
+        let flipped = img.flip_horizontal();
+        assert_eq!(flipped.out_of_bounds(), OutOfBounds::Clamp);
+        assert_eq!(flipped.total_opaque(), 9);
+        for y in 0..5 {
+            for x in 0..5 {
+                assert_eq!(flipped.is_opaque_at(4 - x, y), img.is_opaque_at(x, y));
+            }
+        }
+        // (0, 2) moved to (4, 2).
+        assert!(flipped.is_opaque_at(4, 2));
+        assert!(flipped.contains_pixel(&Rect::from_min_size(Pos2::new(4., 2.), Vec2::new(1., 1.))));
+        assert!(!flipped.contains_pixel(&Rect::from_min_size(Pos2::new(0., 2.), Vec2::new(2., 3.))));
+
+        let flipped = img.flip_vertical();
+        for y in 0..5 {
+            for x in 0..5 {
+                assert_eq!(flipped.is_opaque_at(x, 4 - y), img.is_opaque_at(x, y));
+            }
+        }
+        assert!(!flipped.contains_pixel(&Rect::from_min_size(Pos2::ZERO, Vec2::new(5., 1.))));
+    }
+
+    #[test]
+    fn can_rotate_mask() {
+        // 3x2 with a single opaque pixel at (2, 0):
+        // __O
+        // ___
+        let mut bits = BitSet::with_capacity(6);
+        bits.insert(2);
+        let img = BitImg::new(Pixels2D::new(bits, Rect::from_min_size(Pos2::ZERO, Vec2::new(3., 2.))));
+        let only_opaque = |img: &BitImg| img.opaque_bounds().unwrap();
+        let pixel = |x: f32, y: f32| Rect::from_min_size(Pos2::new(x, y), Vec2::new(1., 1.));
+
+        // The top right corner moves to the bottom right:
+        // __
+        // __
+        // _O
+        let rotated = img.rotate90();
+        assert_eq!(rotated.mask_to_color_image(B, T).size, [2, 3]);
+        assert_eq!(only_opaque(&rotated), pixel(1., 2.));
+
+        let rotated = img.rotate180();
+        assert_eq!(rotated.mask_to_color_image(B, T).size, [3, 2]);
+        assert_eq!(only_opaque(&rotated), pixel(0., 1.));
+
+        let rotated = img.rotate270();
+        assert_eq!(rotated.mask_to_color_image(B, T).size, [2, 3]);
+        assert_eq!(only_opaque(&rotated), pixel(0., 0.));
+
+        let back = img.rotate90().rotate90().rotate90().rotate90();
+        assert_eq!(back.pixels, img.pixels);
+        assert_eq!(img.rotate90().rotate180().pixels, img.rotate270().pixels);
+    }
+
+    #[test]
+    fn can_crop_mask() {
+        let img = sample_5x5();
+
+        // Bottom left 2x2 keeps (1, 3).
+        let cropped = img.crop(&Rect::from_min_size(Pos2::new(0., 3.), Vec2::new(2., 2.)));
+        assert_eq!(cropped.mask_to_color_image(B, T).size, [2, 2]);
+        assert_eq!(cropped.total_opaque(), 1);
+        assert!(cropped.is_opaque_at(1, 0));
+
+        // (1, 3) and (2, 3).
+        let cropped = img.crop(&Rect::from_min_size(Pos2::new(1., 3.), Vec2::new(2., 2.)));
+        assert_eq!(cropped.total_opaque(), 2);
+        assert!(cropped.is_opaque_at(0, 0));
+        assert!(cropped.is_opaque_at(1, 0));
+
+        // Hanging out of the top left corner, (0, 0) lands at (2, 2).
+        let cropped = img.crop(&Rect::from_min_size(Pos2::new(-2., -2.), Vec2::new(3., 3.)));
+        assert_eq!(cropped.total_opaque(), 1);
+        assert!(cropped.is_opaque_at(2, 2));
+
+        let cropped = img.crop(&Rect::from_min_size(Pos2::new(10., 10.), Vec2::new(3., 3.)));
+        assert_eq!(cropped.total_opaque(), 0);
+    }
+
+    #[test]
+    fn can_combine_masks() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(4., 2.));
+        // OO__    _OO_
+        // ____    ___O
+        let a = BitImg::from_color_image(&ColorImage { size: [4, 2], pixels: vec![B, B, T, T, T, T, T, T] });
+        let b = BitImg::from_color_image(&ColorImage { size: [4, 2], pixels: vec![T, B, B, T, T, T, T, B] });
+
+        let union = a.union(&b).unwrap();
+        assert_eq!(union.total_opaque(), 4);
+        assert_eq!(union.opaque_bounds(), Some(rect));
+        assert!(!union.is_opaque_at(3, 0));
+
+        let intersection = a.intersect(&b).unwrap();
+        assert_eq!(intersection.total_opaque(), 1);
+        assert!(intersection.is_opaque_at(1, 0));
+        assert!(!intersection.contains_pixel(&Rect::from_min_size(Pos2::new(2., 0.), Vec2::new(2., 2.))));
+
+        let other_size = BitImg::from_color_image(&ColorImage { size: [2, 4], pixels: vec![B; 8] });
+        assert!(a.union(&other_size).is_none());
+        assert!(a.intersect(&other_size).is_none());
+
+        let mut clamped = BitImg::from_color_image(&ColorImage { size: [4, 2], pixels: vec![B, B, T, T, T, T, T, T] });
+        clamped.set_out_of_bounds(OutOfBounds::Clamp);
+        assert_eq!(clamped.union(&b).unwrap().out_of_bounds(), OutOfBounds::Clamp);
+        assert_eq!(clamped.intersect(&b).unwrap().out_of_bounds(), OutOfBounds::Clamp);
+    }
+
+    #[test]
+    fn can_find_nearest_opaque() {
+        let img = sample_5x5();
+
+        assert_eq!(img.nearest_opaque(Pos2::new(0.5, 0.5), 0.), Some(Pos2::new(0., 0.)));
+        // (2, 3) is sqrt(5) away from the center of (4, 4).
+        assert_eq!(img.nearest_opaque(Pos2::new(4.5, 4.5), 2.3), Some(Pos2::new(2., 3.)));
+        assert_eq!(img.nearest_opaque(Pos2::new(4.5, 4.5), 2.2), None);
+        assert_eq!(img.nearest_opaque(Pos2::new(10., 0.5), 6.), Some(Pos2::new(4., 0.)));
+        assert_eq!(img.nearest_opaque(Pos2::new(0.5, 0.5), -1.), None);
+
+        // Agrees with a brute force scan.
+        let mut bitset = BitSet::with_capacity(400);
+        for i in (0..400).filter(|i| (i * 37) % 23 == 0) {
+            bitset.insert(i);
+        }
+        let pixels = Pixels2D::new(bitset, Rect::from_min_size(Pos2::ZERO, Vec2::new(20., 20.)));
+        let img = BitImg::new(pixels.clone());
+        for from in [Pos2::new(3.2, 17.9), Pos2::new(10., 10.), Pos2::new(-4., 25.)] {
+            let brute = (0..400).filter(|i| pixels.pixel_at(i % 20, i / 20))
+                .map(|i| Pos2::new((i % 20) as f32, (i / 20) as f32))
+                .min_by(|a, b| from.distance_sq(*a + Vec2::splat(0.5)).total_cmp(&from.distance_sq(*b + Vec2::splat(0.5))))
+                .unwrap();
+            let found = img.nearest_opaque(from, 100.).unwrap();
+            assert_eq!(from.distance(found + Vec2::splat(0.5)), from.distance(brute + Vec2::splat(0.5)));
+        }
+    }
+
+    #[test]
+    fn show_paints_and_senses_opaque_pixels() {
+        // O_
+        // __
+        let img = ColorImage { size: [2, 2], pixels: vec![B, T, T, T] };
+        let ctx = Context::default();
+        let img = Img::from_img("test", img, &ctx);
+        // Drawn at 20x the native size.
+        let draw_rect = Rect::from_min_size(Pos2::new(10., 10.), Vec2::new(40., 40.));
+
+        let show_at = |pos: Pos2| {
+            let mut hovered = false;
+            let mut output = None;
+            for _ in 0..2 {
+                let input = egui::RawInput {
+                    events: vec![egui::Event::PointerMoved(pos)],
+                    ..Default::default()
+                };
+                output = Some(ctx.run(input, |ctx| {
+                    egui::CentralPanel::default().show(ctx, |ui| {
+                        hovered = img.show(ui, draw_rect, egui::Sense::click()).hovered();
+                    });
+                }));
+            }
+            let textured = output.unwrap().shapes.iter().any(|clipped| match &clipped.shape {
+                egui::Shape::Mesh(mesh) => mesh.texture_id == img.texture_id(),
+                _ => false,
+            });
+            (hovered, textured)
+        };
+        assert_eq!(show_at(Pos2::new(29., 29.)), (true, true));
+        assert_eq!(show_at(Pos2::new(31., 15.)), (false, true));
+
+        // The same image shown twice gets two ids.
+        let mut ids = Vec::new();
+        let _ = ctx.run(egui::RawInput::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ids.push(img.show(ui, draw_rect, egui::Sense::click()).id);
+                ids.push(img.show(ui, draw_rect.translate(Vec2::new(50., 0.)), egui::Sense::click()).id);
+            });
+        });
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[test]
+    fn img_can_be_added_as_widget() {
+        // O_
+        // __
+        let img = ColorImage { size: [2, 2], pixels: vec![B, T, T, T] };
+        let ctx = Context::default();
+        let img = Img::from_img("test", img, &ctx);
+
+        let add_at = |pos: Pos2, scaled: bool| {
+            let mut response = None;
+            for _ in 0..2 {
+                let input = egui::RawInput {
+                    events: vec![egui::Event::PointerMoved(pos)],
+                    ..Default::default()
+                };
+                let _ = ctx.run(input, |ctx| {
+                    egui::CentralPanel::default().show(ctx, |ui| {
+                        response = Some(if scaled {
+                            ui.add(ImgWidget::new(&img).size(Vec2::new(40., 40.)).sense(egui::Sense::hover()))
+                        } else {
+                            ui.add(&img)
+                        });
+                    });
+                });
+            }
+            let response = response.unwrap();
+            (response.rect, response.hovered())
+        };
+
+        let (rect, hovered) = add_at(Pos2::new(-10., -10.), false);
+        assert_eq!(rect.size(), Vec2::new(2., 2.));
+        assert!(!hovered);
+        assert!(add_at(rect.min + Vec2::new(0.5, 0.5), false).1);
+        assert!(!add_at(rect.min + Vec2::new(1.5, 0.5), false).1);
+
+        let (rect, _) = add_at(Pos2::new(-10., -10.), true);
+        assert_eq!(rect.size(), Vec2::new(40., 40.));
+        assert!(add_at(rect.min + Vec2::new(15., 15.), true).1);
+        assert!(!add_at(rect.min + Vec2::new(25., 15.), true).1);
+    }
+
+    #[test]
+    fn img_button_clicks_only_on_opaque_pixels() {
+        // O_
+        // __
+        let img = ColorImage { size: [2, 2], pixels: vec![B, T, T, T] };
+        let ctx = Context::default();
+        let img = Img::from_img("test", img, &ctx);
+
+        let click_at = |pos: Pos2| {
+            let mut response = None;
+            let frames = [
+                vec![egui::Event::PointerMoved(pos)],
+                vec![egui::Event::PointerButton { pos, button: egui::PointerButton::Primary, pressed: true, modifiers: Default::default() }],
+                vec![egui::Event::PointerButton { pos, button: egui::PointerButton::Primary, pressed: false, modifiers: Default::default() }],
+            ];
+            for events in frames {
+                let input = egui::RawInput { events, ..Default::default() };
+                let _ = ctx.run(input, |ctx| {
+                    egui::CentralPanel::default().show(ctx, |ui| {
+                        response = Some(ui.add(ImgButton::new(&img).size(Vec2::new(20., 20.))));
+                    });
+                });
+            }
+            let response = response.unwrap();
+            (response.rect, response.clicked())
+        };
+
+        let (rect, clicked) = click_at(Pos2::new(-10., -10.));
+        let padding = ctx.style().spacing.button_padding;
+        assert_eq!(rect.size(), Vec2::new(20., 20.) + 2. * padding);
+        assert!(!clicked);
+        let image_min = rect.min + padding;
+        assert!(click_at(image_min + Vec2::new(5., 5.)).1);
+        assert!(!click_at(image_min + Vec2::new(15., 5.)).1);
+        // The frame padding is not part of the image.
+        assert!(!click_at(rect.min + Vec2::new(padding.x / 2., padding.y / 2.)).1);
+    }
+
+    #[test]
+    fn can_map_screen_to_image() {
+        let rect = Rect::from_min_size(Pos2::new(10., 20.), Vec2::new(40., 20.));
+        let size = Vec2::new(4., 4.);
+
+        assert_eq!(screen_to_image(Pos2::new(10., 20.), rect, size), Some((0, 0)));
+        assert_eq!(screen_to_image(Pos2::new(19.9, 24.9), rect, size), Some((0, 0)));
+        assert_eq!(screen_to_image(Pos2::new(20., 25.), rect, size), Some((1, 1)));
+        assert_eq!(screen_to_image(Pos2::new(49.99, 39.99), rect, size), Some((3, 3)));
+        assert_eq!(screen_to_image(Pos2::new(50., 30.), rect, size), None);
+        assert_eq!(screen_to_image(Pos2::new(30., 40.), rect, size), None);
+        assert_eq!(screen_to_image(Pos2::new(9.9, 30.), rect, size), None);
+        assert_eq!(screen_to_image(Pos2::new(30., 30.), rect, Vec2::ZERO), None);
+    }
+
+    #[test]
+    fn can_resize() {
+        // 8x8 with an opaque 4x4 block at the top left and a single pixel at (7, 7).
+        let mut bitset = BitSet::with_capacity(64);
+        for y in 0..4 {
+            for x in 0..4 {
+                bitset.insert(x + y * 8);
+            }
+        }
+        bitset.insert(63);
+        let img = BitImg::new(Pixels2D::new(bitset, Rect::from_min_size(Pos2::ZERO, Vec2::new(8., 8.))));
+
+        let half = img.resize(4, 4);
+        assert_eq!(half.total_opaque(), 5);
+        assert_eq!(half.opaque_bounds(), Some(Rect::from_min_size(Pos2::ZERO, Vec2::new(4., 4.))));
+        assert!(half.contains_pixel(&Rect::from_min_size(Pos2::ZERO, Vec2::new(2., 2.))));
+        assert_eq!(half.opaque_ratio(&Rect::from_min_size(Pos2::ZERO, Vec2::new(2., 2.))), 1.);
+
+        let double = img.resize(16, 16);
+        assert_eq!(double.total_opaque(), 68);
+        assert!(double.is_opaque_at(7, 7));
+        assert!(!double.is_opaque_at(8, 8));
+        assert!(double.is_opaque_at(15, 15));
+
+        assert_eq!(img.resize(0, 3).total_opaque(), 0);
+    }
+
+    #[test]
+    fn can_build_bitset_from_chroma_key() {
+        let m = Color32::from_rgb(255, 0, 255);
+        let near_m = Color32::from_rgb(250, 4, 251);
+        let k = Color32::BLACK;
+        // Magenta background with a black shape.
+        let img = ColorImage {
+            size: [3, 3],
+            pixels: vec![
+                m, k, m,
+                near_m, k, k,
+                m, m, m,
+            ],
+        };
+
+        let bits = to_bitset_chroma(&img, m, 8);
+        assert_eq!(bits.iter().collect::<Vec<_>>(), vec![1, 4, 5]);
+
+        let bits = to_bitset_chroma(&img, m, 0);
+        assert_eq!(bits.iter().collect::<Vec<_>>(), vec![1, 3, 4, 5]);
+    }
+
+    #[test]
+    fn can_invert() {
+        let img = sample_5x5();
+        let inverted = img.invert();
+
+        assert_eq!(inverted.total_opaque(), 25 - img.total_opaque());
+        assert!(!img.is_opaque_at(1, 0));
+        assert!(inverted.is_opaque_at(1, 0));
+        assert!(!inverted.is_opaque_at(0, 0));
+        assert!(inverted.contains_pixel(&Rect::from_min_size(Pos2::new(0., 4.), Vec2::new(5., 1.))));
+        assert_eq!(inverted.invert().total_opaque(), img.total_opaque());
+    }
+
+    #[test]
+    fn opacity_is_defined_by_alpha() {
+        let dark = Color32::from_rgba_premultiplied(0, 0, 0, 200);
+        let additive = Color32::from_rgba_premultiplied(40, 0, 0, 0);
+        let img = ColorImage { size: [3, 1], pixels: vec![dark, additive, T] };
+
+        assert_eq!(to_bitset(&img).iter().collect::<Vec<_>>(), vec![0]);
+        assert_eq!(to_bitmap(&img).iter().collect::<Vec<_>>(), vec![0]);
+
+        let cached = u8_to_img(&img_to_u8(&img).unwrap()).unwrap();
+        assert_eq!(cached.pixels, img.pixels);
+        assert_eq!(cached[(0, 0)].a(), 200);
+        assert_eq!(to_bitset(&cached).iter().collect::<Vec<_>>(), vec![0]);
+        assert!(BitImg::from_color_image(&cached).is_opaque_at(0, 0));
+        assert!(!BitImg::from_color_image(&cached).is_opaque_at(1, 0));
+    }
+
+    #[test]
+    fn svg_loader_groups_entries_by_namespace() {
+        let dir = "clickable_img_namespace_test";
+        clear_cache(dir);
+        let toolbar = SvgLoader::new(0.2, Some(dir)).with_namespace("toolbar").with_prefix("icon");
+        assert_eq!(toolbar.namespace(), Some(std::path::Path::new("toolbar")));
+        assert_eq!(toolbar.prefix(), Some("icon"));
+        assert_eq!(toolbar.entry_name("abc"), "icon_abc");
+        assert_eq!(SvgLoader::new(0.2, Some(dir)).entry_name("abc"), "abc");
+
+        toolbar.load(TEST_SVG).unwrap();
+        assert_eq!(toolbar.cache_stats(), CacheStats { hits: 0, misses: 1 });
+
+        // Another namespace does not see the entry, the same one does.
+        let menu = SvgLoader::new(0.2, Some(dir)).with_namespace("menu").with_prefix("icon");
+        menu.load(TEST_SVG).unwrap();
+        assert_eq!(menu.cache_stats(), CacheStats { hits: 0, misses: 1 });
+        let flat = SvgLoader::new(0.2, Some(dir));
+        flat.load(TEST_SVG).unwrap();
+        assert_eq!(flat.cache_stats(), CacheStats { hits: 0, misses: 1 });
+        let toolbar = SvgLoader::new(0.2, Some(dir)).with_namespace("toolbar").with_prefix("icon");
+        toolbar.load(TEST_SVG).unwrap();
+        assert_eq!(toolbar.cache_stats(), CacheStats { hits: 1, misses: 0 });
+
+        let entries: Vec<String> = std::fs::read_dir(dirs::cache_dir().unwrap().join(dir).join("toolbar")).unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(entries, vec![format!("icon_{}", cache_key(0.2, TEST_SVG))]);
+        clear_cache(dir);
+    }
+
+    #[test]
+    fn svg_loader_can_invalidate_entry() {
+        let dir = "clickable_img_invalidate_test";
+        clear_cache(dir);
+        let loader = SvgLoader::new(0.2, Some(dir));
+        let expected = loader.load(TEST_SVG).unwrap();
+        loader.load(TEST_SVG).unwrap();
+        assert_eq!(loader.cache_stats(), CacheStats { hits: 0, misses: 1 });
+        assert_eq!(loader.renders.load(Ordering::Relaxed), 1);
+
+        loader.invalidate(TEST_SVG).unwrap();
+        assert_eq!(loader.memory_cache_len(), 0);
+        assert_eq!(loader.load(TEST_SVG).unwrap(), expected);
+        assert_eq!(loader.cache_stats(), CacheStats { hits: 0, misses: 2 });
+        assert_eq!(loader.renders.load(Ordering::Relaxed), 2);
+
+        // Invalidating a missing entry or without a disk cache is fine.
+        loader.invalidate(b"<svg/>").unwrap();
+        SvgLoader::new(0.2, None::<&str>).invalidate(TEST_SVG).unwrap();
+        clear_cache(dir);
+    }
+
+    #[test]
+    fn svg_loader_expires_entries() {
+        fn later() -> std::time::SystemTime { std::time::SystemTime::now() + std::time::Duration::from_secs(7200) }
+        let dir = "clickable_img_ttl_test";
+        clear_cache(dir);
+        let mut loader = SvgLoader::new(0.2, Some(dir)).with_ttl(std::time::Duration::from_secs(3600));
+        assert_eq!(loader.ttl(), Some(std::time::Duration::from_secs(3600)));
+        loader.load(TEST_SVG).unwrap();
+        loader.load(TEST_SVG).unwrap();
+        assert_eq!(loader.renders.load(Ordering::Relaxed), 1);
+
+        loader.now = later;
+        loader.load(TEST_SVG).unwrap();
+        assert_eq!(loader.renders.load(Ordering::Relaxed), 2);
+        assert_eq!(loader.cache_stats(), CacheStats { hits: 0, misses: 2 });
+
+        // The overwritten entry is fresh again.
+        let loader = SvgLoader::new(0.2, Some(dir)).with_ttl(std::time::Duration::from_secs(3600));
+        loader.load(TEST_SVG).unwrap();
+        assert_eq!(loader.cache_stats(), CacheStats { hits: 1, misses: 0 });
+
+        // Without a ttl entries never expire.
+        let mut loader = SvgLoader::new(0.2, Some(dir));
+        loader.now = later;
+        loader.load(TEST_SVG).unwrap();
+        assert_eq!(loader.cache_stats(), CacheStats { hits: 1, misses: 0 });
+        clear_cache(dir);
+    }
+
+    #[test]
+    fn svg_loader_bounds_disk_cache() {
+        const SVG_A: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10"><rect width="5" height="5"/></svg>"#;
+        const SVG_B: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10"><rect width="6" height="6"/></svg>"#;
+        const SVG_C: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10"><rect width="7" height="7"/></svg>"#;
+        // Every reading of the clock is a second later than the previous one.
+        fn ticking() -> std::time::SystemTime {
+            static TICKS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+            std::time::UNIX_EPOCH + std::time::Duration::from_secs(2_000_000_000 + TICKS.fetch_add(1, Ordering::Relaxed))
+        }
+        let dir = std::env::temp_dir().join("clickable_img_bounded_cache_test");
+        clear_cache(&dir);
+        let dir_size = || std::fs::read_dir(&dir).unwrap()
+            .map(|e| e.unwrap().metadata().unwrap().len())
+            .sum::<u64>();
+
+        let loader = SvgLoader::new(1.0, Some(&dir));
+        loader.load(SVG_A).unwrap();
+        let entry_len = dir_size();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Room for two entries. A is read again after B is written, so B is the one evicted for C.
+        let mut loader = SvgLoader::new(1.0, Some(&dir)).with_max_cache_bytes(entry_len * 2 + entry_len / 2);
+        loader.now = ticking;
+        assert_eq!(loader.max_cache_bytes(), Some(entry_len * 2 + entry_len / 2));
+        loader.load(SVG_A).unwrap();
+        loader.load(SVG_B).unwrap();
+        loader.memory_cache_clear();
+        loader.load(SVG_A).unwrap();
+        loader.load(SVG_C).unwrap();
+        assert_eq!(loader.cache_stats(), CacheStats { hits: 1, misses: 3 });
+        assert_eq!(dir_size(), entry_len * 2);
+        assert!(loader.entry_path(&cache_key(1.0, SVG_A)).unwrap().exists());
+        assert!(!loader.entry_path(&cache_key(1.0, SVG_B)).unwrap().exists());
+        assert!(loader.entry_path(&cache_key(1.0, SVG_C)).unwrap().exists());
+
+        // Smaller than a single entry, the newest one is still kept.
+        let loader = SvgLoader::new(1.0, Some(&dir)).with_max_cache_bytes(1);
+        loader.load(SVG_B).unwrap();
+        assert_eq!(dir_size(), entry_len);
+        assert!(loader.entry_path(&cache_key(1.0, SVG_B)).unwrap().exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn svg_loader_renders_again_on_broken_entry() {
+        let dir = "clickable_img_broken_entry_test";
+        clear_cache(dir);
+        let loader = SvgLoader::new(0.2, Some(dir));
+        let expected = loader.load(TEST_SVG).unwrap();
+
+        // An entry in the format without the header.
+        let path = loader.entry_path(&cache_key(0.2, TEST_SVG)).unwrap();
+        std::fs::write(&path, [2, 0, 0, 0, 1, 0, 0, 0, 255, 0, 0, 255, 0, 0, 0, 0]).unwrap();
+
+        let loader = SvgLoader::new(0.2, Some(dir));
+        assert_eq!(loader.load(TEST_SVG).unwrap(), expected);
+        assert_eq!(loader.cache_stats(), CacheStats { hits: 0, misses: 1 });
+        assert_eq!(u8_to_img(&std::fs::read(&path).unwrap()).unwrap(), expected);
+        clear_cache(dir);
+    }
+
+    #[test]
+    fn can_load_svg_from_str() {
+        let svg = std::str::from_utf8(TEST_SVG).unwrap();
+        assert_eq!(load_svg_str(svg, 0.5).unwrap(), load_svg_bytes(TEST_SVG, 0.5).unwrap());
+        assert!(load_svg_str("not an svg", 1.0).is_err());
+
+        let ctx = Context::default();
+        let img = Img::from_svg_str("str", svg, 0.5, &ctx).unwrap();
+        assert_eq!(img.size(), Vec2::new(50., 50.));
+        assert!(img.is_opaque_at(0, 0));
+    }
+
+    #[test]
+    fn contains_pixel_on_deep_tree() {
+        let mut bitset = BitSet::with_capacity(256 * 256);
+        bitset.insert(200 * 256 + 37);
+        let pixels = Pixels2D::new(bitset, Rect::from_min_size(Pos2::ZERO, Vec2::new(256., 256.)));
+        let img = BitImg::with_min_node_size(pixels, 1.);
+        assert_eq!(img.tree_stats().unwrap().depth, 16);
+
+        assert!(img.contains_pixel(&Rect::from_min_size(Pos2::new(37., 200.), Vec2::new(1., 1.))));
+        assert!(img.contains_pixel(&Rect::from_min_size(Pos2::new(30., 190.), Vec2::new(20., 20.))));
+        assert!(!img.contains_pixel(&Rect::from_min_size(Pos2::new(38., 200.), Vec2::new(100., 56.))));
+        assert!(!img.contains_pixel(&Rect::from_min_size(Pos2::ZERO, Vec2::new(256., 200.))));
+    }
+
+    #[test]
+    fn contains_pixel_batch_matches_single_queries() {
+        let img = sample_5x5();
+
+        // Every 2x2 cell of a grid covering the image and beyond.
+        let rects: Vec<Rect> = (-1..3).flat_map(|y| (-1..3).map(move |x|
+            Rect::from_min_size(Pos2::new(x as f32 * 2., y as f32 * 2.), Vec2::new(2., 2.))
+        )).collect();
+        let expected: Vec<bool> = rects.iter().map(|rect| img.contains_pixel(rect)).collect();
+        assert_eq!(img.contains_pixel_batch(&rects), expected);
+        assert!(expected.contains(&true) && expected.contains(&false));
+        assert!(img.contains_pixel_batch(&[]).is_empty());
+    }
+
+    #[test]
+    fn can_find_node_at() {
+        let img = sample_5x5();
+        let root = img.layered_rect().unwrap();
+        assert_eq!(root.rect(), Rect::from_min_size(Pos2::ZERO, Vec2::new(5., 5.)));
+        assert_eq!(root.pixel_count(), 9);
+
+        // Split into 2x5 and 3x5, then 2x2, 2x3, 3x2 and 3x3 leaves.
+        let leaf = root.node_at(Pos2::new(0.5, 0.5)).unwrap();
+        assert_eq!(leaf.rect(), Rect::from_min_size(Pos2::ZERO, Vec2::new(2., 2.)));
+        assert_eq!(leaf.pixel_count(), 1);
+        let leaf = root.node_at(Pos2::new(2., 2.)).unwrap();
+        assert_eq!(leaf.rect(), Rect::from_min_size(Pos2::new(2., 2.), Vec2::new(3., 3.)));
+        assert_eq!(leaf.pixel_count(), 2);
+        assert!(root.node_at(Pos2::new(5., 1.)).is_none());
+        assert!(root.node_at(Pos2::new(-0.1, 1.)).is_none());
+    }
+
+    #[test]
+    fn can_traverse_with_accessors() {
+        let img = sample_5x5();
+        let root = img.layered_rect().unwrap();
+        assert!(!root.is_leaf());
+        let children = root.children().unwrap();
+        assert_eq!(children.len(), 2);
+        assert_eq!(children.iter().map(|child| child.pixel_count()).sum::<usize>(), root.pixel_count());
+
+        // Collect the leaves without matching on the variants.
+        let mut leaves = Vec::new();
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            match node.children() {
+                Some(children) => stack.extend(children),
+                None => {
+                    assert!(node.is_leaf());
+                    leaves.push(node.rect());
+                },
+            }
+        }
+        assert_eq!(leaves.len(), img.tree_stats().unwrap().leaf_count);
+        assert_eq!(leaves.iter().map(|rect| rect.area()).sum::<f32>(), 25.);
+    }
+
+    #[test]
+    fn can_iterate_opaque_pixels() {
         let img = ColorImage {
             size: [8, 16],
             pixels: vec![
-                //  0  1  2  3  4  5  6  7    
+                //  0  1  2  3  4  5  6  7
                 T, T, T, T, T, T, T, T, // 0
                 T, T, T, T, T, T, T, T, // 1
                 T, T, B, T, T, T, B, B, // 2
@@ -350,265 +5228,184 @@ mod tests {
                 T, T, T, T, T, T, T, T, // 15
             ],
         };
-        let bitset = to_bitset(&img);
-        assert!(!bitset.contains(0));
-        assert!(bitset.contains(18));
-        assert!(bitset.contains(22));
-        assert!(bitset.contains(23));
-        assert!(bitset.contains(30));
-        assert!(bitset.contains(49));
-        assert!(bitset.contains(50));
+        let img = BitImg::from_color_image(&img);
+
+        assert_eq!(
+            img.opaque_pixels().collect::<Vec<_>>(),
+            vec![(2, 2), (6, 2), (7, 2), (6, 3), (1, 6), (2, 6), (7, 12), (0, 14), (1, 14)]
+        );
+        assert_eq!(img.opaque_pixels().count(), img.total_opaque());
     }
 
     #[test]
-    fn is_opaque() {
+    fn can_find_connected_components() {
+        // OO___
+        // O____
+        // ___O_
+        // ____O
         let img = ColorImage {
-            size: [8, 16],
+            size: [5, 4],
             pixels: vec![
-                //  0  1  2  3  4  5  6  7    
-                T, T, T, T, T, T, T, T, // 0
-                T, T, T, T, T, T, T, T, // 1
-                T, T, B, T, T, T, B, B, // 2
-                T, T, T, T, T, T, B, T, // 3
-                T, T, T, T, T, T, T, T, // 4
-                T, T, T, T, T, T, T, T, // 5
-                T, B, B, T, T, T, T, T, // 6
-                T, T, T, T, T, T, T, T, // 7
-                T, T, T, T, T, T, T, T, // 8
-                T, T, T, T, T, T, T, T, // 9
-                T, T, T, T, T, T, T, T, // 10
-                T, T, T, T, T, T, T, T, // 11
-                T, T, T, T, T, T, T, B, // 12
-                T, T, T, T, T, T, T, T, // 13
-                B, B, T, T, T, T, T, T, // 14
-                T, T, T, T, T, T, T, T, // 15
+                B, B, T, T, T,
+                B, T, T, T, T,
+                T, T, T, B, T,
+                T, T, T, T, B,
             ],
         };
+        let img = BitImg::from_color_image(&img);
 
-        let ctx = Context::default();
-        let img = Img::from_img("test", img, &ctx);
+        assert_eq!(img.connected_components(Connectivity::Eight), vec![
+            Component { pixel_count: 3, bounds: Rect::from_min_size(Pos2::ZERO, Vec2::new(2., 2.)) },
+            Component { pixel_count: 2, bounds: Rect::from_min_size(Pos2::new(3., 2.), Vec2::new(2., 2.)) },
+        ]);
 
-        assert!(!img.is_opaque_at(0, 0));
-        assert!(img.is_opaque_at(2, 2));
-        assert!(img.is_opaque_at(6, 2));
-        assert!(img.is_opaque_at(6, 2));
-        assert!(img.is_opaque_at(0, 14));
-        assert!(img.is_opaque_at(1, 14));
-        assert!(!img.is_opaque_at(7, 15));
-    }
+        // The diagonal pair is not connected through the corner.
+        let components = img.connected_components(Connectivity::Four);
+        assert_eq!(components.len(), 3);
+        assert_eq!(components[1], Component { pixel_count: 1, bounds: Rect::from_min_size(Pos2::new(3., 2.), Vec2::new(1., 1.)) });
 
-    #[test]
-    fn small_bitimg_becomes_leaf() {
-        // O__
-        // __O
-        // OOO
-        let mut bit_set = BitSet::with_capacity(9);
-        bit_set.insert(0);
-        bit_set.insert(5);
-        bit_set.insert(6);
-        bit_set.insert(7);
-        bit_set.insert(8);
-        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(3., 3.));
-        let bit_img = Pixels2D::new(bit_set, rect);
-        let layered = LayeredRect::new(rect, &bit_img);
-        assert_eq!(layered, LayeredRect::Leaf { rect, pixel_count: 5 });
+        assert!(BitImg::from_color_image(&ColorImage { size: [2, 2], pixels: vec![T; 4] }).connected_components(Connectivity::Four).is_empty());
     }
 
     #[test]
-    fn bitimg_split_horizontal() {
-        // O__O
-        // __O_
-        // OOO_
-        let mut bit_set = BitSet::with_capacity(12);
-        bit_set.insert(0);
-        bit_set.insert(3);
-        bit_set.insert(6);
-        bit_set.insert(8);
-        bit_set.insert(9);
-        bit_set.insert(10);
-
-        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(4., 3.));
-        let bit_img = Pixels2D::new(bit_set, rect);
-        let layered = LayeredRect::new(rect, &bit_img);
-        let [left, right] = match layered {
-            LayeredRect::Leaf { rect: _, pixel_count: _ } => { panic!("Test failed."); },
-            LayeredRect::Node { rect: r, children, pixel_count } => {
-                assert_eq!(rect, r);
-                assert_eq!(pixel_count, 6);
-                children
-            },
-        };
-
-        // Left
-        // O_
-        // __
-        // OO
-        match *left {
-            LayeredRect::Node { rect: _, children: _, pixel_count: _ } => { panic!("Test failed."); },
-            LayeredRect::Leaf { rect, pixel_count } => {
-                assert_eq!(pixel_count, 3);
-                assert_eq!(rect, Rect::from_min_size(Pos2::ZERO, Vec2::new(2., 3.)));
-            },
-        };
+    fn can_dilate() {
+        // An isolated pixel at (2, 2) and one at the corner.
+        let mut bitset = BitSet::with_capacity(36);
+        bitset.insert(2 + 2 * 6);
+        bitset.insert(35);
+        let img = BitImg::new(Pixels2D::new(bitset, Rect::from_min_size(Pos2::ZERO, Vec2::new(6., 6.))));
 
-        // Right
-        // _O
-        // O_
-        // O_
-        match *right {
-            LayeredRect::Node { rect: _, children: _, pixel_count: _ } => { panic!("Test failed."); },
-            LayeredRect::Leaf { rect, pixel_count } => {
-                assert_eq!(pixel_count, 3);
-                assert_eq!(rect, Rect::from_min_size(Pos2::new(2., 0.), Vec2::new(2., 3.)));
-            },
+        let dilated = img.dilate(1);
+        for y in 1..4 {
+            for x in 1..4 {
+                assert!(dilated.is_opaque_at(x, y));
+            }
         }
-    }
-
-    #[test]
-    fn pixels2d_can_determine_rect_contains_pixels() {
-        // O__
-        // __O
-        // OOO
-        let mut bitset = BitSet::with_capacity(9);
-        bitset.insert(0);
-        bitset.insert(5);
-        bitset.insert(6);
-        bitset.insert(7);
-        bitset.insert(8);
+        assert!(!dilated.is_opaque_at(0, 0));
+        assert!(!dilated.is_opaque_at(2, 4));
+        // Clipped at the image border.
+        assert_eq!(dilated.total_opaque(), 9 + 4);
+        assert!(dilated.is_opaque_at(4, 4));
 
-        let pixels = Pixels2D::new(bitset, Rect::from_min_size(Pos2::ZERO, Vec2::new(3., 3.)));
-        assert!(pixels.contains_pixel(&Rect::from_min_size(Pos2::ZERO, Vec2::new(1.0, 1.0))));
-        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(1.0, 1.0))));
-        assert!(pixels.contains_pixel(&Rect::from_min_size(Pos2::ZERO, Vec2::new(1.0, 2.0))));
-        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(1.0, 2.0))));
-        assert!(pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(1.0, 3.0))));
-        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(2.0, 1.0))));
-        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(2.0, 0.0), Vec2::new(2.0, 1.0))));
-        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(10.0, 10.0), Vec2::new(20.0, 20.0))));
+        assert_eq!(img.dilate(0).opaque_pixels().collect::<Vec<_>>(), img.opaque_pixels().collect::<Vec<_>>());
+        assert_eq!(img.dilate(10).total_opaque(), 36);
     }
 
     #[test]
-    fn bitimg_can_determine_rect_contains_pixels() {
-        // O___O
-        // __OO_
-        // OOO__
-        // _OO__
-        // _____
-        let mut bitset = BitSet::with_capacity(25);
-        bitset.insert(0);
-        bitset.insert(4);
-        bitset.insert(7);
+    fn can_erode() {
+        // A filled 5x5 square at (1, 1) and a speck at (8, 0).
+        let mut bitset = BitSet::with_capacity(90);
+        for y in 1..6 {
+            for x in 1..6 {
+                bitset.insert(x + y * 9);
+            }
+        }
         bitset.insert(8);
-        bitset.insert(10);
-        bitset.insert(11);
-        bitset.insert(12);
-        bitset.insert(16);
-        bitset.insert(17);
+        let img = BitImg::new(Pixels2D::new(bitset, Rect::from_min_size(Pos2::ZERO, Vec2::new(9., 10.))));
 
-        let pixels = Pixels2D::new(bitset, Rect::from_min_size(Pos2::ZERO, Vec2::new(5., 5.)));
-        assert!(pixels.contains_pixel(&Rect::from_min_size(Pos2::ZERO, Vec2::new(1.0, 1.0))));
-        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(1.0, 1.0))));
-        assert!(pixels.contains_pixel(&Rect::from_min_size(Pos2::ZERO, Vec2::new(1.0, 2.0))));
-        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(1.0, 2.0))));
-        assert!(pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(1.0, 3.0))));
-        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(1.0, 0.0), Vec2::new(2.0, 1.0))));
-        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(2.0, 0.0), Vec2::new(2.0, 1.0))));
-        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(3.0, 2.0), Vec2::new(2.0, 2.0))));
-        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(0.0, 4.0), Vec2::new(2.0, 2.0))));
-        assert!(pixels.contains_pixel(&Rect::from_min_size(Pos2::new(0.0, 3.0), Vec2::new(3.0, 3.0))));
-        assert!(!pixels.contains_pixel(&Rect::from_min_size(Pos2::new(10.0, 10.0), Vec2::new(20.0, 20.0))));
-    }
-    
-    const TEST_SVG: &'static [u8] = br#"<?xml version="1.0" standalone="no"?>
-    <!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd" >
-    <svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" version="1.1" viewBox="0 0 100 100">
-       <rect x="0" y="0" width="99" height="99" style="fill:rgb(255,0,0);stroke-width:1"/>
-    </svg>
-    "#;
-    
-    #[test]
-    fn img_contains_pixel() {
-        let img: ColorImage = load_svg_bytes(TEST_SVG, 1.0).unwrap();
-        let ctx = Context::default();
-        let img: Img = Img::from_img("test", img, &ctx);
-        assert!(!img.contains_pixel(&Rect::from_min_size(Pos2::new(99., 0.), Vec2::new(1., 1.))));
-        assert!(img.contains_pixel(&Rect::from_min_size(Pos2::new(0., 0.), Vec2::new(1., 1.))));
+        let eroded = img.erode(1);
+        assert_eq!(eroded.total_opaque(), 9);
+        assert_eq!(eroded.opaque_bounds(), Some(Rect::from_min_size(Pos2::new(2., 2.), Vec2::new(3., 3.))));
+        assert!(!eroded.is_opaque_at(1, 1));
+        assert!(!eroded.is_opaque_at(8, 0));
+        assert_eq!(img.erode(2).opaque_pixels().collect::<Vec<_>>(), vec![(3, 3)]);
+        assert_eq!(img.erode(3).total_opaque(), 0);
+
+        // Opening removes the speck and restores the square.
+        let opened = eroded.dilate(1);
+        assert_eq!(opened.total_opaque(), 25);
+        assert_eq!(opened.opaque_bounds(), Some(Rect::from_min_size(Pos2::new(1., 1.), Vec2::new(5., 5.))));
     }
 
     #[test]
-    fn svg_to_img() {
-        let img: ColorImage = load_svg_bytes(TEST_SVG, 1.0).unwrap();
-        assert_eq!(img.width(), 100);
-        assert_eq!(img.height(), 100);
-        assert_eq!(img[(0, 0)], Color32::RED);
-        assert_eq!(img[(98, 0)], Color32::RED);
-        assert_eq!(img[(99, 0)], Color32::TRANSPARENT);
-
-        assert_eq!(img[(0, 98)], Color32::RED);
-        assert_eq!(img[(0, 99)], Color32::TRANSPARENT);
+    fn can_compare_masks() {
+        let ctx = Context::default();
+        let a = Img::from_img("a", ColorImage { size: [2, 1], pixels: vec![B, T] }, &ctx);
+        // Different color, same opacity.
+        let b = Img::from_img("b", ColorImage { size: [2, 1], pixels: vec![Color32::RED, T] }, &ctx);
+        let c = Img::from_img("c", ColorImage { size: [2, 1], pixels: vec![T, B] }, &ctx);
+        let d = Img::from_img("d", ColorImage { size: [1, 2], pixels: vec![B, T] }, &ctx);
 
-        assert_eq!(img[(98, 98)], Color32::RED);
-        assert_eq!(img[(99, 99)], Color32::TRANSPARENT);
+        assert!(a.same_mask(&b));
+        assert!(a.same_mask(&a));
+        assert!(!a.same_mask(&c));
+        assert!(!a.same_mask(&d));
     }
 
     #[test]
-    fn can_cache() {
-        let img: ColorImage = load_svg_bytes(TEST_SVG, 0.1).unwrap();
-        if let Err(e) = LocalFileCache::<()>::invalidate("my_test").unwrap() {
-            if e.kind() != ErrorKind::NotFound {
-                panic!("Unexpected error {:?}", e);
-            }
-        }
-        let loader = SvgLoader::new(0.1, Some("my_test"));
-        let cached = loader.load(TEST_SVG).unwrap();
-
-        assert_eq!(img.size, cached.size);
-        assert_eq!(img.pixels, cached.pixels);
+    fn can_retain_colors() {
+        let ctx = Context::default();
+        let img = ColorImage { size: [2, 1], pixels: vec![Color32::RED, T] };
 
-        let cached = loader.load(TEST_SVG).unwrap();
-        assert_eq!(img.size, cached.size);
-        assert_eq!(img.pixels, cached.pixels);
+        let retained = Img::from_img_retaining_colors("retained", img.clone(), egui::TextureOptions::NEAREST, &ctx);
+        assert_eq!(retained.color_image(), Some(&img));
+        assert_eq!(retained.color_at(0, 0), Some(Color32::RED));
+        assert_eq!(retained.color_at(1, 0), Some(T));
+        assert_eq!(retained.color_at(2, 0), None);
+        assert_eq!(retained.color_at(0, 1), None);
+        assert!(retained.is_opaque_at(0, 0));
 
-        for x in 0..cached.width() {
-            for y in 0..cached.height() {
-                assert_eq!(cached[(x, y)], Color32::RED);
-            }
-        }
+        let lean = Img::from_img("lean", img, &ctx);
+        assert!(lean.color_image().is_none());
+        assert_eq!(lean.color_at(0, 0), None);
     }
 
     #[test]
-    fn do_split_horizontal() {
-        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(10.0, 5.0));
-        let [left, right] = crate::split_horizontal(&rect);
-        assert_eq!(left, Rect::from_min_size(Pos2::ZERO, Vec2::new(5.0, 5.0)));
-        assert_eq!(right, Rect::from_min_size(Pos2::new(5.0, 0.0), Vec2::new(5.0, 5.0)));
+    fn can_detect_fully_opaque_and_transparent() {
+        const SOLID_SVG: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10" viewBox="0 0 10 10"><rect width="10" height="10" fill="blue"/></svg>"#;
+        let ctx = Context::default();
+        let solid = Img::from_svg("solid", SOLID_SVG, 2.0, &ctx).unwrap();
+        assert!(solid.is_fully_opaque());
+        assert!(!solid.is_fully_transparent());
 
-        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(5.0, 3.0));
-        let [left, right] = crate::split_horizontal(&rect);
-        assert_eq!(left, Rect::from_min_size(Pos2::ZERO, Vec2::new(2.0, 3.0)));
-        assert_eq!(right, Rect::from_min_size(Pos2::new(2.0, 0.0), Vec2::new(3.0, 3.0)));
+        let empty = Img::from_img("empty", ColorImage { size: [3, 2], pixels: vec![T; 6] }, &ctx);
+        assert!(empty.is_fully_transparent());
+        assert!(!empty.is_fully_opaque());
 
-        let rect = Rect::from_min_size(Pos2::new(100.0, 200.0), Vec2::new(10.0, 5.0));
-        let [left, right] = crate::split_horizontal(&rect);
-        assert_eq!(left, Rect::from_min_size(Pos2::new(100.0, 200.0), Vec2::new(5.0, 5.0)));
-        assert_eq!(right, Rect::from_min_size(Pos2::new(105.0, 200.0), Vec2::new(5.0, 5.0)));
+        // TEST_SVG leaves the last row and column transparent.
+        let partial = Img::from_svg("partial", TEST_SVG, 1.0, &ctx).unwrap();
+        assert!(!partial.is_fully_opaque());
+        assert!(!partial.is_fully_transparent());
     }
 
     #[test]
-    fn do_split_vertical() {
-        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(10.0, 20.0));
-        let [left, right] = crate::split_vertical(&rect);
-        assert_eq!(left, Rect::from_min_size(Pos2::ZERO, Vec2::new(10.0, 10.0)));
-        assert_eq!(right, Rect::from_min_size(Pos2::new(0.0, 10.0), Vec2::new(10.0, 10.0)));
+    fn tolerates_zero_size_images() {
+        let ctx = Context::default();
+        for size in [[0, 0], [0, 5], [5, 0]] {
+            let color = ColorImage { size, pixels: vec![] };
+            let img = BitImg::from_color_image(&color);
+            assert_eq!(img.total_opaque(), 0);
+            assert!(img.is_fully_transparent());
+            assert!(!img.is_fully_opaque());
+            assert!(!img.contains_pixel(&Rect::from_min_size(Pos2::ZERO, Vec2::new(10., 10.))));
+            assert!(!img.is_opaque_at(0, 0));
+            assert_eq!(img.opaque_bounds(), None);
+            assert_eq!(img.first_opaque_in(&Rect::from_min_size(Pos2::ZERO, Vec2::new(10., 10.))), None);
+            assert_eq!(img.nearest_opaque(Pos2::ZERO, 10.), None);
+            assert!(!img.contains_pixel_in_circle(Pos2::ZERO, 10.));
+            assert_eq!(img.opaque_ratio(&Rect::from_min_size(Pos2::ZERO, Vec2::new(10., 10.))), 0.);
+            assert!(img.connected_components(Connectivity::Eight).is_empty());
+            assert_eq!(img.tree_stats().unwrap().leaf_count, 1);
+            assert_eq!(img.dilate(1).total_opaque(), 0);
+            assert_eq!(img.erode(1).total_opaque(), 0);
+            assert_eq!(img.invert().total_opaque(), 0);
+            assert_eq!(img.rotate90().total_opaque(), 0);
+            assert_eq!(img.resize(3, 3).total_opaque(), 0);
+            assert_eq!(img.row_histogram().iter().sum::<usize>(), 0);
+            assert_eq!(img.column_histogram().iter().sum::<usize>(), 0);
+            assert!(img.holes(Connectivity::Four).is_empty());
+            assert!(img.leaves_intersecting(&Rect::from_min_size(Pos2::ZERO, Vec2::new(10., 10.))).unwrap().is_empty());
+            assert_eq!(img.mask_to_color_image(B, T).size, size);
+            assert_eq!(img.iou(&img), 1.);
+            let _ = img.tree_to_svg().unwrap();
+            let _ = img.to_dot().unwrap();
 
-        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(10.0, 11.0));
-        let [left, right] = crate::split_vertical(&rect);
-        assert_eq!(left, Rect::from_min_size(Pos2::ZERO, Vec2::new(10.0, 5.0)));
-        assert_eq!(right, Rect::from_min_size(Pos2::new(0.0, 5.0), Vec2::new(10.0, 6.0)));
+            let lazy = BitImg::with_options(Pixels2D::from_bitmap(to_bitmap(&color), Rect::from_min_size(Pos2::ZERO, Vec2::new(size[0] as f32, size[1] as f32))), TreeOptions { lazy: true, ..TreeOptions::default() });
+            assert!(!lazy.contains_pixel(&Rect::from_min_size(Pos2::ZERO, Vec2::new(10., 10.))));
 
-        let rect = Rect::from_min_size(Pos2::new(100.0, 200.0), Vec2::new(10.0, 5.0));
-        let [left, right] = crate::split_vertical(&rect);
-        assert_eq!(left, Rect::from_min_size(Pos2::new(100.0, 200.0), Vec2::new(10.0, 2.0)));
-        assert_eq!(right, Rect::from_min_size(Pos2::new(100.0, 202.0), Vec2::new(10.0, 3.0)));
+            let img = Img::from_img("empty", color, &ctx);
+            assert_eq!(img.total_opaque(), 0);
+            assert!(!img.contains_screen_pos(Pos2::ZERO, Rect::from_min_size(Pos2::ZERO, Vec2::new(10., 10.))));
+        }
     }
 }